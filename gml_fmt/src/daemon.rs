@@ -0,0 +1,313 @@
+use gml_fmt_lib::{Diagnostic, FormatResult, LangConfig, Span, TextEdit};
+use interprocess::local_socket::{prelude::*, GenericFilePath, ListenerOptions, Name};
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+use std::{fs, thread};
+
+/// How many formatted files we remember before starting over.
+const CACHE_ENTRIES: usize = 512;
+
+/// The files which can change how a directory, or any directory under it, is formatted.
+const CONFIG_FILES: [&str; 4] = ["gml_fmt.toml", ".gml_fmt.toml", ".gml_fmt", ".editorconfig"];
+
+/// Where we listen when we're not told otherwise: a socket file on Unix, and a named pipe on Windows.
+pub fn default_socket() -> PathBuf {
+    if cfg!(windows) {
+        return PathBuf::from(r"\\.\pipe\gml_fmt");
+    }
+
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("gml_fmt.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("gml_fmt-{}.sock", user))
+        }
+    }
+}
+
+/// Listens on `socket`, and answers one newline-delimited JSON request after another on every
+/// connection, until we're killed.
+pub fn serve(socket: &Path) -> io::Result<()> {
+    let listener = match ListenerOptions::new().name(socket_name(socket)?).create_sync() {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            // a socket file can outlive the daemon which made it, but we mustn't steal one in use.
+            if LocalSocketStream::connect(socket_name(socket)?).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("a daemon is already listening on {}", socket.display()),
+                ));
+            }
+            fs::remove_file(socket)?;
+            ListenerOptions::new().name(socket_name(socket)?).create_sync()?
+        }
+        listener => listener?,
+    };
+    eprintln!("gml_fmt daemon listening on {}", socket.display());
+
+    let daemon = Arc::new(Daemon::new());
+    for connection in listener.incoming() {
+        let connection = match connection {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("gml_fmt daemon: {}", e);
+                continue;
+            }
+        };
+
+        let daemon = Arc::clone(&daemon);
+        thread::spawn(move || {
+            let mut reader = BufReader::new(connection);
+            let mut line = String::new();
+
+            while matches!(reader.read_line(&mut line), Ok(read) if read != 0) {
+                let reply = match serde_json::from_str::<Value>(&line) {
+                    // a panic is a bug in gml_fmt, but it shouldn't take the connection down with it.
+                    Ok(request) => panic::catch_unwind(AssertUnwindSafe(|| daemon.handle(&request))).unwrap_or_else(|_| {
+                        json!({ "id": request.get("id"), "error": { "message": "gml_fmt panicked formatting this file" } })
+                    }),
+                    Err(e) => json!({ "error": { "message": format!("invalid request: {}", e) } }),
+                };
+                line.clear();
+
+                let connection = reader.get_mut();
+                if writeln!(connection, "{}", reply)
+                    .and_then(|_| connection.flush())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn socket_name(socket: &Path) -> io::Result<Name<'_>> {
+    socket.to_fs_name::<GenericFilePath>()
+}
+
+/// The directory a source was formatted for, that directory's config fingerprint, and a hash of the source.
+type CacheKey = (PathBuf, Vec<Option<SystemTime>>, u64);
+
+/// A directory's `LangConfig`, and the modification times of the config files it could have been
+/// built from when we read it.
+type ConfigEntry = (Vec<Option<SystemTime>>, Arc<LangConfig>);
+
+/// Shared by every connection. Each map is only locked long enough to look in it or add to it,
+/// so connections format at the same time.
+struct Daemon {
+    lang_configs: Mutex<HashMap<PathBuf, ConfigEntry>>,
+    /// Sources and their formatted output. The source is kept so that two which hash the same
+    /// can't be mistaken for each other.
+    formatted: Mutex<HashMap<CacheKey, (String, String)>>,
+    fallback_directory: PathBuf,
+}
+
+impl Daemon {
+    fn new() -> Daemon {
+        Daemon {
+            lang_configs: Mutex::new(HashMap::new()),
+            formatted: Mutex::new(HashMap::new()),
+            fallback_directory: std::env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    /// Answers a request like `{"id": 1, "path": "scr_a.gml", "source": "...", "range": {"start": 0, "end": 10}}`.
+    /// `source` is read from `path` if it's left out, and `range` (byte offsets into `source`)
+    /// limits formatting to the changes which touch it.
+    fn handle(&self, request: &Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let path = PathBuf::from(request["path"].as_str().unwrap_or_default());
+
+        let directory = path
+            .parent()
+            .filter(|directory| directory.is_dir())
+            .unwrap_or(&self.fallback_directory);
+        let directory = directory.canonicalize().unwrap_or_else(|_| directory.to_path_buf());
+        let fingerprint = config_fingerprint(&directory);
+        let lang_config = match self.lang_config(&directory, &fingerprint) {
+            Ok(lang_config) => lang_config,
+            Err(message) => return json!({ "id": id, "error": { "message": message } }),
+        };
+
+        let source = match request["source"].as_str() {
+            Some(source) => source.to_owned(),
+            None => match gml_fmt_lib::read_source(&path, &lang_config) {
+                Ok(source) => source,
+                Err(e) => return json!({ "id": id, "error": { "message": format!("could not read file: {}", e) } }),
+            },
+        };
+
+        let formatted = match self.format((directory, fingerprint, hash(&source)), &lang_config, &source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                let stripped = source.strip_prefix('\u{feff}').unwrap_or(&source);
+                let diagnostic = Diagnostic::from_format_error(&path, stripped, &e).to_json();
+                return json!({ "id": id, "error": serde_json::from_str::<Value>(&diagnostic).unwrap() });
+            }
+        };
+
+        let formatted = match request.get("range") {
+            Some(range) => {
+                let range = Span::new(
+                    range["start"].as_u64().unwrap_or_default() as usize,
+                    range["end"].as_u64().unwrap_or(u64::MAX) as usize,
+                );
+                let edits: Vec<TextEdit> = gml_fmt_lib::edits_between(&source, &formatted)
+                    .into_iter()
+                    .filter(|edit| edit.span.start <= range.end && edit.span.end >= range.start)
+                    .collect();
+                gml_fmt_lib::apply_edits(&source, &edits)
+            }
+            None => formatted,
+        };
+
+        json!({ "id": id, "formatted": formatted })
+    }
+
+    fn format(&self, key: CacheKey, lang_config: &LangConfig, source: &str) -> FormatResult<String> {
+        if let Some((cached_source, formatted)) = lock(&self.formatted).get(&key) {
+            if cached_source == source {
+                return Ok(formatted.clone());
+            }
+        }
+
+        let formatted = if source.contains("// @gml_fmt ignore") {
+            source.to_owned()
+        } else {
+            gml_fmt_lib::run(source, lang_config, None)?
+        };
+
+        let mut cache = lock(&self.formatted);
+        if cache.len() >= CACHE_ENTRIES {
+            cache.clear();
+        }
+        cache.insert(key, (source.to_owned(), formatted.clone()));
+
+        Ok(formatted)
+    }
+
+    /// The `LangConfig` for `directory`, read again if any config file it could come from has changed since.
+    fn lang_config(&self, directory: &Path, fingerprint: &[Option<SystemTime>]) -> Result<Arc<LangConfig>, String> {
+        if let Some((read_at, lang_config)) = lock(&self.lang_configs).get(directory) {
+            if read_at == fingerprint {
+                return Ok(Arc::clone(lang_config));
+            }
+        }
+
+        let lang_config = Arc::new(LangConfig::find(directory, None)?);
+        lock(&self.lang_configs).insert(
+            directory.to_path_buf(),
+            (fingerprint.to_vec(), Arc::clone(&lang_config)),
+        );
+
+        Ok(lang_config)
+    }
+}
+
+/// Locks one of our maps. Nothing can panic while one's locked, but if something ever did, the
+/// map would still be fine to use, so we carry on rather than failing every request after it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// When each config file in `directory` and every directory above it was last changed, since the
+/// `gml_fmt.toml` can come from any of them, and the `.editorconfig` from all of them. Files
+/// which aren't there count too, so that adding one is noticed.
+fn config_fingerprint(directory: &Path) -> Vec<Option<SystemTime>> {
+    directory
+        .ancestors()
+        .flat_map(|this_dir| CONFIG_FILES.iter().map(move |name| this_dir.join(name)))
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Only needs to be the same within one run of the daemon, so the standard library's hasher is fine.
+fn hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod daemon_test {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let daemon = Daemon::new();
+
+        let reply = daemon.handle(&json!({ "id": 1, "path": "scr_a.gml", "source": "x=1\ny=2\n" }));
+        assert_eq!(reply, json!({ "id": 1, "formatted": "x = 1;\ny = 2;\n" }));
+        // the second time comes from the cache.
+        assert_eq!(
+            daemon.handle(&json!({ "id": 1, "path": "scr_a.gml", "source": "x=1\ny=2\n" })),
+            reply
+        );
+
+        let reply = daemon.handle(&json!({
+            "id": 2,
+            "source": "x=1\ny=2\n",
+            "range": { "start": 4, "end": 7 },
+        }));
+        assert_eq!(reply["formatted"], "x=1\ny = 2;\n");
+
+        let reply = daemon.handle(&json!({ "id": 3, "path": "scr_a.gml", "source": "x = (" }));
+        assert_eq!(reply["id"], 3);
+        assert_eq!(reply["error"]["file"], "scr_a.gml");
+        assert_eq!(reply["error"]["byte_start"], 5);
+    }
+
+    #[test]
+    fn bad_configs() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_daemon_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("gml_fmt.toml"), "space_size = \"four\"").unwrap();
+        let daemon = Daemon::new();
+
+        let reply = daemon.handle(&json!({ "id": 1, "path": directory.join("scr_a.gml"), "source": "x=1\n" }));
+        assert_eq!(reply["id"], 1);
+        assert!(reply["error"]["message"].as_str().unwrap().contains("gml_fmt.toml"));
+
+        // and everything else carries on as normal.
+        let reply = daemon.handle(&json!({ "id": 2, "path": "scr_a.gml", "source": "x=1\n" }));
+        assert_eq!(reply, json!({ "id": 2, "formatted": "x = 1;\n" }));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn project_configs() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_daemon_project_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("scripts/scr_a")).unwrap();
+        fs::write(
+            directory.join("gml_fmt.toml"),
+            "space_size = 2\nencoding = \"windows-1252\"\n",
+        )
+        .unwrap();
+        let script = directory.join("scripts/scr_a/scr_a.gml");
+        fs::write(&script, b"if (a) {\n    b = \"\xe9\";\n}\n").unwrap();
+        let daemon = Daemon::new();
+
+        // the config is the project's, and the file is read in the encoding it asks for.
+        let reply = daemon.handle(&json!({ "id": 1, "path": script }));
+        assert_eq!(reply["formatted"], "if (a) {\n  b = \"\u{e9}\";\n}\n");
+
+        // an `.editorconfig` further up, which wasn't there before, changes the output.
+        fs::write(directory.join("scripts/.editorconfig"), "[*]\nindent_style = tab\n").unwrap();
+        let reply = daemon.handle(&json!({ "id": 2, "path": script }));
+        assert_eq!(reply["formatted"], "if (a) {\n\tb = \"\u{e9}\";\n}\n");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Writes a pre-commit hook into the repository around `directory`, which formats the GML a
+/// commit touches and stages the result, so that only files people are already changing get
+/// formatted. We won't replace a hook that's already there unless `force` is set.
+///
+/// The hook formats the files in the working tree, so it refuses to run when a staged file
+/// also has changes which aren't staged, rather than committing them along with the formatting.
+pub fn install(directory: &Path, force: bool) -> Result<PathBuf, String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--git-path", "hooks"])
+        .current_dir(directory)
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+    if output.status.success() == false {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+
+    // this is relative to `directory`, unless `core.hooksPath` is absolute.
+    let hooks = directory.join(String::from_utf8_lossy(&output.stdout).trim_end());
+    let hook = hooks.join("pre-commit");
+    if hook.exists() && force == false {
+        return Err(format!(
+            "{} already exists. Pass --force to overwrite it.",
+            hook.display()
+        ));
+    }
+
+    let gml_fmt = std::env::current_exe().map_err(|e| format!("could not find gml_fmt: {}", e))?;
+    fs::create_dir_all(&hooks).map_err(|e| format!("could not create {}: {}", hooks.display(), e))?;
+    fs::write(&hook, script(&gml_fmt)).map_err(|e| format!("could not write {}: {}", hook.display(), e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("could not make {} executable: {}", hook.display(), e))?;
+    }
+
+    Ok(hook)
+}
+
+/// The hook itself. Git runs it with `sh` (even on Windows), from the top of the repository.
+fn script(gml_fmt: &Path) -> String {
+    let gml_fmt = gml_fmt.display().to_string().replace('\\', "/").replace('\'', r"'\''");
+
+    format!(
+        r#"#!/bin/sh
+# Formats the GML this commit touches. Written by `gml_fmt install-hook`.
+staged=$(git diff --cached --name-only --diff-filter=ACMR -- '*.gml' '*.gmx')
+[ -z "$staged" ] && exit 0
+
+echo "$staged" | while IFS= read -r file; do
+    if ! git diff --quiet -- "$file"; then
+        echo "gml_fmt: $file has changes which aren't staged. Stage or stash them, and commit again." >&2
+        exit 1
+    fi
+done || exit 1
+
+echo "$staged" | while IFS= read -r file; do
+    '{}' -f "$file" || exit 1
+    git add -- "$file"
+done
+"#,
+        gml_fmt
+    )
+}
@@ -0,0 +1,613 @@
+use gml_fmt_lib::{Cst, Diagnostic, LangConfig, TextEdit, TokenType};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+// and the LSP's own, for a request we understood but couldn't carry out.
+const REQUEST_FAILED: i64 = -32803;
+
+const BOM: char = '\u{feff}';
+
+/// Speaks the Language Server Protocol over stdin and stdout until the client tells us to exit.
+/// Returns the exit code the protocol asks for.
+pub fn serve() -> i32 {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = stdout.lock();
+    let mut server = Server::new();
+
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            // the client went away without asking us to exit.
+            Ok(None) => return 1,
+            Err(e) => {
+                eprintln!("gml_fmt lsp: {}", e);
+                return 1;
+            }
+        };
+
+        let message = match serde_json::from_slice::<Value>(&message) {
+            Ok(message) => message,
+            Err(e) => {
+                send(&mut output, &error_response(Value::Null, PARSE_ERROR, &e.to_string()));
+                continue;
+            }
+        };
+
+        // a panic is a bug in gml_fmt, but it shouldn't take the editor's server down with it.
+        let replies =
+            panic::catch_unwind(AssertUnwindSafe(|| server.handle(&message))).unwrap_or_else(|_| {
+                match message.get("id") {
+                    Some(id) => vec![error_response(
+                        id.clone(),
+                        REQUEST_FAILED,
+                        "gml_fmt panicked handling this request",
+                    )],
+                    None => Vec::new(),
+                }
+            });
+        for reply in replies {
+            send(&mut output, &reply);
+        }
+
+        if let Some(code) = server.exit_code {
+            return code;
+        }
+    }
+}
+
+struct Server {
+    /// The text of every open document, by URI.
+    documents: HashMap<String, String>,
+    root: PathBuf,
+    shutdown_requested: bool,
+    exit_code: Option<i32>,
+}
+
+impl Server {
+    fn new() -> Server {
+        Server {
+            documents: HashMap::new(),
+            root: std::env::current_dir().unwrap_or_default(),
+            shutdown_requested: false,
+            exit_code: None,
+        }
+    }
+
+    /// Handles one message, and returns the messages to send back.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // a response to a request of ours, and we never make any.
+            None if message.get("id").is_some() && message.get("result").is_some() => return Vec::new(),
+            None => return vec![error_response(message["id"].clone(), INVALID_REQUEST, "no method")],
+        };
+        let params = &message["params"];
+
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.notification(method, params),
+        };
+
+        let result = match method {
+            "initialize" => Ok(self.initialize(params)),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(Value::Null)
+            }
+            "textDocument/formatting" => self.format(params, None),
+            "textDocument/rangeFormatting" => {
+                let range = &params["range"];
+                let first = line_of(&range["start"]);
+                let mut last = line_of(&range["end"]);
+                // a selection which ends at the start of a line doesn't include that line.
+                if last > first && range["end"]["character"].as_u64() == Some(0) {
+                    last -= 1;
+                }
+                self.format(params, Some((first, last)))
+            }
+            "textDocument/onTypeFormatting" => {
+                let lines = self.typed_lines(params);
+                self.format(params, lines)
+            }
+            _ => {
+                return vec![error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("unknown method {}", method),
+                )]
+            }
+        };
+
+        match result {
+            Ok(result) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            Err(message) => vec![error_response(id, REQUEST_FAILED, &message)],
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_owned();
+
+        match method {
+            "exit" => {
+                self.exit_code = Some(if self.shutdown_requested { 0 } else { 1 });
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default().to_owned();
+                self.documents.insert(uri.clone(), text);
+                vec![self.diagnostics(&uri)]
+            }
+            "textDocument/didChange" => {
+                let document = self.documents.entry(uri.clone()).or_default();
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    match change.get("range") {
+                        Some(range) => {
+                            let start = position_to_offset(document, &range["start"]);
+                            let end = position_to_offset(document, &range["end"]).max(start);
+                            document.replace_range(start..end, text);
+                        }
+                        None => *document = text.to_owned(),
+                    }
+                }
+                vec![self.diagnostics(&uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![publish_diagnostics(&uri, Vec::new())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        let root = params["rootUri"]
+            .as_str()
+            .and_then(uri_to_path)
+            .or_else(|| params["rootPath"].as_str().map(PathBuf::from));
+        if let Some(root) = root.filter(|root| root.is_dir()) {
+            self.root = root;
+        }
+
+        json!({
+            "capabilities": {
+                // we always want the whole document.
+                "textDocumentSync": 1,
+                "documentFormattingProvider": true,
+                "documentRangeFormattingProvider": true,
+                "documentOnTypeFormattingProvider": {
+                    "firstTriggerCharacter": ";",
+                    "moreTriggerCharacter": ["}"],
+                },
+            },
+            "serverInfo": { "name": "gml_fmt", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    /// Formats a document, and returns the edits which touch `lines` (or every edit, without them).
+    /// A config file we can't read is an error, rather than a reason to format some other way.
+    fn format(&self, params: &Value, lines: Option<(usize, usize)>) -> Result<Value, String> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let source = match self.documents.get(uri) {
+            Some(source) if !source.contains("// @gml_fmt ignore") => source,
+            _ => return Ok(json!([])),
+        };
+        let lang_config = self.lang_config(uri)?;
+
+        // a document which doesn't parse already has a diagnostic saying why.
+        match gml_fmt_lib::format_to_edits(source, &lang_config) {
+            Ok(edits) => Ok(Value::Array(lsp_edits(source, edits, lines))),
+            Err(_) => Ok(json!([])),
+        }
+    }
+
+    /// The lines to tidy up after a `;` or `}` is typed: the `;`'s line, or the whole block a `}` closes.
+    fn typed_lines(&self, params: &Value) -> Option<(usize, usize)> {
+        let line = line_of(&params["position"]);
+        if params["ch"].as_str() != Some("}") {
+            return Some((line, line));
+        }
+
+        let source = self.documents.get(params["textDocument"]["uri"].as_str()?)?;
+        // the CST leaves out any BOM.
+        let typed_at = position_to_offset(source, &params["position"]).saturating_sub(bom_len(source));
+        let cst = Cst::parse(source);
+        let mut depth = 0;
+        let opening_brace = cst
+            .tokens()
+            .into_iter()
+            .rev()
+            .skip_while(|token| token.token.span.end > typed_at)
+            .find(|token| {
+                match token.token.token_type {
+                    TokenType::RightBrace => depth += 1,
+                    TokenType::LeftBrace => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })?;
+
+        Some((opening_brace.token.line_number as usize, line))
+    }
+
+    /// The config for a document: the nearest `gml_fmt.toml` between its directory and the
+    /// workspace root, like the one at the top of a project.
+    fn lang_config(&self, uri: &str) -> Result<LangConfig, String> {
+        let directory = uri_to_path(uri)
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .filter(|directory| directory.is_dir())
+            .unwrap_or_else(|| self.root.clone());
+
+        LangConfig::find(&directory, Some(&self.root))
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let source = self.documents.get(uri).map(String::as_str).unwrap_or_default();
+        let stripped = source.strip_prefix(BOM).unwrap_or(source);
+
+        // with recovery on, what doesn't parse is kept as it is, rather than being an error. The
+        // config failing to load is reported when formatting, so here it just means the defaults.
+        let lang_config = self.lang_config(uri).unwrap_or_default();
+        let parsed = gml_fmt_lib::parse_with(stripped, &lang_config);
+
+        let diagnostics = match parsed {
+            Ok(_) => Vec::new(),
+            Err(e) => {
+                let path = uri_to_path(uri).unwrap_or_default();
+                let message = Diagnostic::from_format_error(&path, stripped, &e).message;
+                let span = e.span().unwrap_or_default();
+                let bom = bom_len(source);
+
+                vec![json!({
+                    "range": {
+                        "start": offset_to_position(source, span.start + bom),
+                        "end": offset_to_position(source, span.end + bom),
+                    },
+                    "severity": 1,
+                    "source": "gml_fmt",
+                    "message": message,
+                })]
+            }
+        };
+
+        publish_diagnostics(uri, diagnostics)
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let content_length =
+        content_length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Content-Length"))?;
+    let mut content = vec![0; content_length];
+    input.read_exact(&mut content)?;
+
+    Ok(Some(content))
+}
+
+fn send(output: &mut impl Write, message: &Value) {
+    let message = message.to_string();
+    let result = write!(output, "Content-Length: {}\r\n\r\n{}", message.len(), message).and_then(|_| output.flush());
+    if let Err(e) = result {
+        eprintln!("gml_fmt lsp: {}", e);
+    }
+}
+
+/// Turns our edits into LSP ones. With `lines`, only the edits which touch those lines
+/// (inclusive, and 0-based) are kept.
+fn lsp_edits(source: &str, edits: Vec<TextEdit>, lines: Option<(usize, usize)>) -> Vec<Value> {
+    let line_of_offset = |offset: usize| source[..offset].matches('\n').count();
+
+    edits
+        .into_iter()
+        .filter(|edit| match lines {
+            Some((first, last)) => line_of_offset(edit.span.start) <= last && line_of_offset(edit.span.end) >= first,
+            None => true,
+        })
+        .map(|edit| {
+            json!({
+                "range": {
+                    "start": offset_to_position(source, edit.span.start),
+                    "end": offset_to_position(source, edit.span.end),
+                },
+                "newText": edit.new_text,
+            })
+        })
+        .collect()
+}
+
+fn line_of(position: &Value) -> usize {
+    position["line"].as_u64().unwrap_or_default() as usize
+}
+
+fn bom_len(source: &str) -> usize {
+    if source.starts_with(BOM) {
+        BOM.len_utf8()
+    } else {
+        0
+    }
+}
+
+/// LSP positions count UTF-16 code units along a line, so we convert to and from byte offsets.
+fn offset_to_position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn position_to_offset(text: &str, position: &Value) -> usize {
+    let line = line_of(position);
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+
+    let line_start = match line {
+        0 => 0,
+        _ => match text.match_indices('\n').nth(line - 1) {
+            Some((i, _)) => i + 1,
+            None => return text.len(),
+        },
+    };
+
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (
+            byte,
+            tail.get(..2)
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()),
+        ) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+
+    // `file:///C:/Users` is `C:/Users` on Windows.
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => Some(PathBuf::from(&path[1..])),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+#[cfg(test)]
+mod lsp_test {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let text = "a = \"é😀\";\nb";
+
+        assert_eq!(offset_to_position(text, 12), json!({ "line": 0, "character": 9 }));
+        assert_eq!(
+            offset_to_position(text, text.len()),
+            json!({ "line": 1, "character": 1 })
+        );
+        assert_eq!(position_to_offset(text, &json!({ "line": 0, "character": 9 })), 12);
+        assert_eq!(position_to_offset(text, &json!({ "line": 0, "character": 99 })), 13);
+        assert_eq!(
+            position_to_offset(text, &json!({ "line": 5, "character": 0 })),
+            text.len()
+        );
+    }
+
+    #[test]
+    fn edits() {
+        let old = "a=1;\nb=2;\nc=3";
+        let edits = gml_fmt_lib::edits_between(old, "a = 1;\nb=2;\nc=3;\n");
+
+        assert_eq!(
+            lsp_edits(old, edits.clone(), None),
+            vec![
+                json!({
+                    "range": { "start": { "line": 0, "character": 1 }, "end": { "line": 0, "character": 1 } },
+                    "newText": " ",
+                }),
+                json!({
+                    "range": { "start": { "line": 0, "character": 2 }, "end": { "line": 0, "character": 2 } },
+                    "newText": " ",
+                }),
+                json!({
+                    "range": { "start": { "line": 2, "character": 3 }, "end": { "line": 2, "character": 3 } },
+                    "newText": ";\n",
+                }),
+            ]
+        );
+        assert_eq!(lsp_edits(old, edits, Some((1, 2))).len(), 1);
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(
+            uri_to_path("file:///home/me/My%20Game/scr_a.gml"),
+            Some(PathBuf::from("/home/me/My Game/scr_a.gml"))
+        );
+        assert_eq!(uri_to_path("file:///c%3A/game"), Some(PathBuf::from("c:/game")));
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+
+    #[test]
+    fn session() {
+        let mut server = Server::new();
+        let uri = "file:///nowhere/scr_a.gml";
+
+        let published = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text": "x = (" } },
+        }));
+        assert_eq!(
+            published[0]["params"]["diagnostics"][0]["range"]["start"]["character"],
+            5
+        );
+
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": uri },
+                "contentChanges": [{ "text": "if (a) {\nb=1;\n c=2;\n}\nd=3;\n" }],
+            },
+        }));
+        let reply = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/onTypeFormatting",
+            "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": 3, "character": 1 },
+                "ch": "}",
+                "options": { "tabSize": 4, "insertSpaces": true },
+            },
+        }));
+        // the block is tidied, but not `d=3;` after it.
+        let edits = reply[0]["result"].as_array().unwrap();
+        assert!(!edits.is_empty());
+        for edit in edits {
+            let line = edit["range"]["start"]["line"].as_u64().unwrap();
+            assert!(line == 1 || line == 2, "{}", edit);
+        }
+
+        let reply = server.handle(&json!({ "jsonrpc": "2.0", "id": 2, "method": "workspace/symbol" }));
+        assert_eq!(reply[0]["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn project_config() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_lsp_project_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("scripts/scr_a")).unwrap();
+        std::fs::write(
+            directory.join("gml_fmt.toml"),
+            "space_size = 2\nrecover_from_errors = true\n",
+        )
+        .unwrap();
+        let uri = format!("file://{}/scripts/scr_a/scr_a.gml", directory.display());
+
+        let mut server = Server::new();
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": { "rootUri": format!("file://{}", directory.display()) },
+        }));
+
+        // the statement which doesn't parse is kept as it is, so it isn't an error.
+        let published = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text": "x = (];\nif (a) {\n    b = 1;\n}\n" } },
+        }));
+        assert_eq!(published[0]["params"]["diagnostics"], json!([]));
+
+        // and the indentation comes from the project's config, two directories up.
+        let reply = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/formatting",
+            "params": { "textDocument": { "uri": uri }, "options": { "tabSize": 4, "insertSpaces": true } },
+        }));
+        assert!(reply[0]["result"]
+            .as_array()
+            .is_some_and(|edits| edits.is_empty() == false));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn bad_config() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_lsp_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("gml_fmt.toml"), "space_size = \"four\"\n").unwrap();
+        let uri = format!("file://{}/scr_a.gml", directory.display());
+
+        let mut server = Server::new();
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text": "x=1" } },
+        }));
+        let reply = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/formatting",
+            "params": { "textDocument": { "uri": uri }, "options": { "tabSize": 4, "insertSpaces": true } },
+        }));
+        assert_eq!(reply[0]["error"]["code"], REQUEST_FAILED);
+        assert!(reply[0]["error"]["message"].as_str().unwrap().contains("gml_fmt.toml"));
+
+        // the server is still there for the next request, once the file is fixed.
+        std::fs::write(directory.join("gml_fmt.toml"), "space_size = 4\n").unwrap();
+        let reply = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/formatting",
+            "params": { "textDocument": { "uri": uri }, "options": { "tabSize": 4, "insertSpaces": true } },
+        }));
+        assert!(reply[0]["result"]
+            .as_array()
+            .is_some_and(|edits| edits.is_empty() == false));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
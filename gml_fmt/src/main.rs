@@ -1,6 +1,12 @@
-use clap::{App, Arg};
-use gml_fmt_lib::{Config, LangConfig, PrintFlags};
-use std::{path::PathBuf, process};
+#![allow(clippy::bool_comparison)]
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use gml_fmt_lib::{CodebaseStats, Config, Emit, LangConfig, MessageFormat, PrintFlags};
+use std::{fs, path::PathBuf, process};
+
+mod daemon;
+mod hook;
+mod lsp;
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
@@ -31,11 +37,133 @@ fn main() {
                 .short("n")
                 .help("Do not overwrite the original file. Mostly used in conjungtion with -l to log output."),
         )
+        .arg(
+            Arg::with_name("verify-idempotent")
+                .long("verify-idempotent")
+                .help("Formats every file a second time, and reports any file whose output changes again"),
+        )
+        .arg(
+            Arg::with_name("recover")
+                .long("recover")
+                .help("Leaves statements which can't be parsed as they are, and formats the rest of the file"),
+        )
+        .arg(
+            Arg::with_name("no-cache")
+                .long("no-cache")
+                .help("Formats every file, instead of skipping the ones which haven't changed since the last run"),
+        )
+        .arg(
+            Arg::with_name("changed")
+                .long("changed")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .value_name("BASE")
+                .help("Only formats files git says have changed: since BASE if given (like --changed=main), or else since the last commit"),
+        )
+        .arg(
+            Arg::with_name("yy")
+                .long("yy")
+                .help("Also formats the project's .yy and .yyp files into the layout GameMaker writes them in"),
+        )
+        .arg(
+            Arg::with_name("message-format")
+                .long("message-format")
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .help("Prints problems for people to read, or as one JSON object per line for tools"),
+        )
+        .arg(
+            Arg::with_name("emit")
+                .long("emit")
+                .takes_value(true)
+                .possible_values(&["ast-json", "tokens-json"])
+                .help("Prints each file's syntax tree or tokens as one line of JSON, instead of formatting it"),
+        )
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("Writes a gml_fmt.toml which matches how an existing project is already formatted")
+                .arg(
+                    Arg::with_name("PATH")
+                        .help("Sets the project directory to scan. Leave blank to use the current directory.")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Overwrite an existing gml_fmt.toml"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("install-hook")
+                .about("Writes a git pre-commit hook which formats the GML files each commit changes")
+                .arg(
+                    Arg::with_name("PATH")
+                        .help("Sets a directory inside the repository. Leave blank to use the current directory.")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Overwrite an existing pre-commit hook"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lsp")
+                .about("Runs a Language Server Protocol server over stdin and stdout, for editors to format with"),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Stays running and formats JSON requests sent over a local socket, so that tools skip the start-up cost")
+                .arg(
+                    Arg::with_name("socket")
+                        .long("socket")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .help("Sets the socket file (or named pipe on Windows) to listen on"),
+                ),
+        )
         .get_matches();
 
     // Get our path and make our lang_config file
     let our_path = std::env::current_dir().unwrap();
-    let lang_config = LangConfig::new(&our_path);
+
+    if let Some(init_matches) = matches.subcommand_matches("init") {
+        init(init_matches, our_path);
+        return;
+    }
+    if let Some(hook_matches) = matches.subcommand_matches("install-hook") {
+        let directory = hook_matches.value_of("PATH").map_or(our_path, PathBuf::from);
+        match hook::install(&directory, hook_matches.is_present("force")) {
+            Ok(hook) => println!("Wrote {:?}.", hook),
+            Err(e) => {
+                eprintln!("Could not install the hook: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if matches.subcommand_matches("lsp").is_some() {
+        process::exit(lsp::serve());
+    }
+    if let Some(daemon_matches) = matches.subcommand_matches("daemon") {
+        let socket = daemon_matches
+            .value_of("socket")
+            .map_or_else(daemon::default_socket, PathBuf::from);
+        if let Err(e) = daemon::serve(&socket) {
+            eprintln!("Could not start the daemon: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let mut lang_config = LangConfig::load(&our_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    if matches.is_present("recover") {
+        lang_config.recover_from_errors = true;
+    }
 
     // Get Path
     let input_path = if matches.is_present("PATH") {
@@ -66,14 +194,45 @@ fn main() {
         print_flags.insert(PrintFlags::LOG_AST);
     }
 
-    let config = Config::new(input_path, print_flags, do_file).unwrap_or_else(|e| {
+    if matches.is_present("verify-idempotent") {
+        print_flags.insert(PrintFlags::VERIFY_IDEMPOTENT);
+    }
+
+    if matches.is_present("yy") {
+        print_flags.insert(PrintFlags::FORMAT_YY);
+    }
+
+    let mut config = Config::new(input_path.clone(), print_flags, do_file).unwrap_or_else(|e| {
         eprintln!("File reading error: {}", e);
         process::exit(1);
     });
 
+    if matches.is_present("changed") {
+        if let Err(e) = config.retain_changed(&input_path, matches.value_of("changed")) {
+            eprintln!("Could not ask git which files changed: {}", e);
+            process::exit(1);
+        }
+    }
+
+    if matches.is_present("no-cache") {
+        config.cache = None;
+    }
+
+    if matches.value_of("message-format") == Some("json") {
+        config.message_format = MessageFormat::Json;
+    }
+
+    config.emit = match matches.value_of("emit") {
+        Some("ast-json") => Some(Emit::AstJson),
+        Some("tokens-json") => Some(Emit::TokensJson),
+        _ => None,
+    };
+
     match gml_fmt_lib::run_with_config(&config, &lang_config) {
         Ok(()) => {
-            println!("Format complete.");
+            if config.message_format == MessageFormat::Human && config.emit.is_none() {
+                println!("Format complete.");
+            }
         }
         Err(err) => {
             eprintln!("Error: {}", err);
@@ -81,3 +240,39 @@ fn main() {
         }
     };
 }
+
+fn init(matches: &ArgMatches, our_path: PathBuf) {
+    let project_path = match matches.value_of("PATH") {
+        Some(path) => PathBuf::from(path),
+        None => our_path,
+    };
+
+    let config_path = project_path.join("gml_fmt.toml");
+    if config_path.exists() && !matches.is_present("force") {
+        eprintln!("{:?} already exists. Pass --force to overwrite it.", config_path);
+        process::exit(1);
+    }
+
+    let config = Config::new(project_path, PrintFlags::empty(), false).unwrap_or_else(|e| {
+        eprintln!("File reading error: {}", e);
+        process::exit(1);
+    });
+
+    let stats = CodebaseStats::gather(&config.files);
+    println!("{}", stats);
+
+    let lang_config = stats.best_lang_config();
+    let output = format!(
+        "# Generated by `gml_fmt init` from {} files.\n\
+         # gml_fmt always places braces K&R style and leaves string quotes as written.\n{}",
+        stats.files,
+        lang_config.to_toml()
+    );
+
+    if let Err(e) = fs::write(&config_path, output) {
+        eprintln!("Could not write {:?}: {}", config_path, e);
+        process::exit(1);
+    }
+
+    println!("Wrote {:?}.", config_path);
+}
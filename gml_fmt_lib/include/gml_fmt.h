@@ -0,0 +1,46 @@
+/*
+ * gml_fmt, as a shared library: libgml_fmt_lib.so, libgml_fmt_lib.dylib or gml_fmt_lib.dll,
+ * built with `cargo build --release -p gml_fmt_lib`.
+ */
+#ifndef GML_FMT_H
+#define GML_FMT_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define GML_FMT_OK 0
+/* A pointer we needed was null, or the source had a NUL byte in it. */
+#define GML_FMT_INVALID_ARGUMENT 1
+/* The source or the config wasn't UTF-8. */
+#define GML_FMT_INVALID_UTF8 2
+/* The config wasn't a gml_fmt.toml we could read. */
+#define GML_FMT_INVALID_CONFIG 3
+/* The source couldn't be parsed, so it wasn't formatted. */
+#define GML_FMT_PARSE_ERROR 4
+/* gml_fmt hit a bug. Please report it along with the source! */
+#define GML_FMT_INTERNAL_ERROR 5
+
+/*
+ * Formats the `len` bytes of UTF-8 at `src`, with `config_toml` (the contents of a gml_fmt.toml,
+ * or NULL for the defaults).
+ *
+ * On success, returns GML_FMT_OK and points `*output` at the formatted code. Otherwise, returns
+ * one of the other GML_FMT_ codes and points `*output` at a message saying what went wrong.
+ * Either way, `*output` is NUL-terminated, `*output_len` (if it isn't NULL) is its length without
+ * the NUL, and it must be given back to gml_fmt_free.
+ *
+ * It's safe to call from several threads at once.
+ */
+int gml_fmt_format(const char *src, size_t len, const char *config_toml, char **output, size_t *output_len);
+
+/* Frees a string which gml_fmt_format gave back. Does nothing with NULL. */
+void gml_fmt_free(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GML_FMT_H */
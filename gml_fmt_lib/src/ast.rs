@@ -0,0 +1,80 @@
+use super::cst;
+use super::error::FormatResult;
+use super::lang_config::LangConfig;
+use super::line_endings::{self, SourceLayout};
+use super::parser::Parser;
+use super::printer::Printer;
+use super::scanner::Dialect;
+use super::statements::StmtBox;
+
+/// A parsed GML file.
+///
+/// Every `Span` in it is a byte range into `source()`, which is the source it was parsed from
+/// without any UTF-8 BOM.
+#[derive(Debug)]
+pub struct Ast<'a> {
+    pub statements: Vec<StmtBox<'a>>,
+    source: &'a str,
+    layout: SourceLayout,
+    dialect: Dialect,
+}
+
+impl<'a> Ast<'a> {
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    pub(crate) fn has_bom(&self) -> bool {
+        self.layout.has_bom
+    }
+
+    pub(crate) fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+}
+
+/// Parses GML source into an `Ast`, failing on the first statement we can't parse.
+pub fn parse(source: &str) -> FormatResult<Ast<'_>> {
+    parse_with(source, &LangConfig::default())
+}
+
+/// Parses GML source into an `Ast`. Any statement we can't parse is kept as a
+/// `Statement::Verbatim` holding its source text, and we carry on from the next one.
+pub fn parse_with_recovery(source: &str) -> FormatResult<Ast<'_>> {
+    parse_with(
+        source,
+        &LangConfig {
+            recover_from_errors: true,
+            ..LangConfig::default()
+        },
+    )
+}
+
+/// Parses GML source into an `Ast` the way `lang_config` asks: in its dialect, and recovering
+/// from statements we can't parse if it says to.
+pub fn parse_with<'a>(source: &'a str, lang_config: &LangConfig) -> FormatResult<Ast<'a>> {
+    let layout = SourceLayout::detect(source);
+    let source = line_endings::strip_bom(source);
+
+    let mut parser = Parser::new(source).with_dialect(lang_config.dialect);
+    if lang_config.recover_from_errors {
+        parser = parser.recover_from_errors();
+    }
+
+    Ok(Ast {
+        statements: parser.build_ast()?,
+        source,
+        layout,
+        dialect: lang_config.dialect,
+    })
+}
+
+/// Prints an `Ast` back out as formatted GML, with the same line endings and BOM
+/// the source it was parsed from had (unless `lang_config` says otherwise).
+pub fn format_ast(ast: &Ast<'_>, lang_config: &LangConfig) -> String {
+    let source_size = ast.source.len();
+    let printer = Printer::new(source_size / 2, lang_config).autoformat(&ast.statements);
+    let output = cst::restore_comments(ast, &printer.get_output(source_size));
+
+    ast.layout.restore(output, lang_config.line_endings)
+}
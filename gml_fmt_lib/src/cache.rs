@@ -0,0 +1,147 @@
+use super::lang_config::LangConfig;
+use fnv::FnvHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+const HEADER: &str = "# gml_fmt cache. It's safe to delete, and best left out of source control.";
+
+/// Remembers the hash of every file as we last left it, so that files which haven't changed
+/// since don't need to be read, parsed and printed again. It's only good for as long as the
+/// settings are the same, so changing the `LangConfig` or gml_fmt itself starts it over.
+pub(crate) struct Cache {
+    path: PathBuf,
+    settings: u64,
+    previous: HashMap<PathBuf, u64>,
+    current: HashMap<PathBuf, u64>,
+}
+
+impl Cache {
+    /// Reads the cache at `path`, if there is one, and if it was made with the same settings.
+    pub(crate) fn open(path: PathBuf, lang_config: &LangConfig) -> Cache {
+        let settings = hash(&format!("{}\n{}", env!("CARGO_PKG_VERSION"), lang_config.to_toml()));
+        let mut previous = HashMap::new();
+
+        if let Ok(contents) = fs::read_to_string(&path) {
+            let mut lines = contents.lines().filter(|line| line.starts_with('#') == false);
+            if lines.next() == Some(&format!("{:016x}", settings)) {
+                for line in lines {
+                    if let Some((hash, file)) = line.split_once('\t') {
+                        if let Ok(hash) = u64::from_str_radix(hash, 16) {
+                            previous.insert(PathBuf::from(file), hash);
+                        }
+                    }
+                }
+            }
+        }
+
+        Cache {
+            path,
+            settings,
+            previous,
+            current: HashMap::new(),
+        }
+    }
+
+    /// Whether `file` is just as we left it, in which case there's nothing to do.
+    pub(crate) fn is_fresh(&mut self, file: &Path, contents: &str) -> bool {
+        let key = self.key(file);
+        let contents_hash = hash(contents);
+
+        if self.previous.get(&key) == Some(&contents_hash) {
+            self.current.insert(key, contents_hash);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Remembers that we left `file` formatted as `contents`.
+    pub(crate) fn record(&mut self, file: &Path, contents: &str) {
+        let key = self.key(file);
+        self.current.insert(key, hash(contents));
+    }
+
+    /// Writes the files we've seen this run back out, along with any we didn't see (a `--changed`
+    /// run only looks at a few) which still exist.
+    pub(crate) fn save(&self) -> std::io::Result<()> {
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let unseen = self
+            .previous
+            .iter()
+            .filter(|(file, _)| self.current.contains_key(*file) == false && directory.join(file).is_file());
+
+        let mut entries: Vec<_> = self.current.iter().chain(unseen).collect();
+        if entries.len() == self.previous.len()
+            && entries
+                .iter()
+                .all(|(file, hash)| self.previous.get(*file) == Some(hash))
+        {
+            return Ok(());
+        }
+        entries.sort();
+
+        let mut contents = format!("{}\n{:016x}\n", HEADER, self.settings);
+        for (file, hash) in entries {
+            contents.push_str(&format!("{:016x}\t{}\n", hash, file.display()));
+        }
+
+        fs::write(&self.path, contents)
+    }
+
+    /// Files are remembered relative to the cache, so it works wherever gml_fmt is run from.
+    fn key(&self, file: &Path) -> PathBuf {
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+        file.strip_prefix(directory).unwrap_or(file).to_path_buf()
+    }
+}
+
+/// FNV, rather than the standard library's hasher, since it has to stay the same between builds.
+fn hash(text: &str) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(text.as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod cache_test {
+    use super::*;
+
+    #[test]
+    fn remembers_files() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_cache_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(".gml_fmt_cache");
+        let a = directory.join("a.gml");
+
+        let mut cache = Cache::open(path.clone(), &LangConfig::default());
+        assert!(cache.is_fresh(&a, "x = 1;\n") == false);
+        cache.record(&a, "x = 1;\n");
+        cache.save().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("\ta.gml\n"));
+
+        let mut cache = Cache::open(path.clone(), &LangConfig::default());
+        assert!(cache.is_fresh(&a, "x = 1;\n"));
+        assert!(cache.is_fresh(&a, "x = 2;\n") == false);
+
+        // a run which only looks at some files keeps what it knew about the others.
+        fs::write(&a, "x = 1;\n").unwrap();
+        let mut cache = Cache::open(path.clone(), &LangConfig::default());
+        cache.record(&directory.join("b.gml"), "y = 1;\n");
+        cache.save().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("\ta.gml\n") && saved.contains("\tb.gml\n"));
+
+        // different settings mean different output, so everything has to be formatted again.
+        let lang_config = LangConfig {
+            space_size: 2,
+            ..LangConfig::default()
+        };
+        let mut cache = Cache::open(path, &lang_config);
+        assert!(cache.is_fresh(&a, "x = 1;\n") == false);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,10 +1,29 @@
+use super::diagnostics::MessageFormat;
+use super::emit::Emit;
+use super::git;
+use super::gmx;
+use super::project;
+use super::scanner::Dialect;
+use super::yy;
 use bitflags;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::{ffi::OsStr, fs};
 
+/// The name of the cache we keep next to a project, or in the directory we're formatting.
+pub const CACHE_FILE: &str = ".gml_fmt_cache";
+
 pub struct Config {
     pub files: Vec<PathBuf>,
     pub print_flags: PrintFlags,
+    pub message_format: MessageFormat,
+    /// When set, we print this for every file instead of formatting it.
+    pub emit: Option<Emit>,
+    /// Where we remember which files are already formatted, so that we can skip them next time.
+    /// `None` formats every file every time.
+    pub cache: Option<PathBuf>,
+    /// The dialect the project's code is written in, which beats the one in the `LangConfig`.
+    pub dialect: Option<Dialect>,
 }
 
 impl Config {
@@ -12,6 +31,10 @@ impl Config {
         let mut config = Config {
             files: Vec::new(),
             print_flags,
+            message_format: MessageFormat::default(),
+            emit: None,
+            cache: None,
+            dialect: None,
         };
 
         if input_path.exists() == false {
@@ -24,8 +47,21 @@ impl Config {
             }
 
             (true, false) => {
+                config.cache = Some(input_path.join(CACHE_FILE));
+
+                if let Some(yyp) = project::find_yyp(&input_path)? {
+                    config.take_in_project(&yyp)?;
+                    return Ok(config);
+                }
+                if let Some(project_gmx) = gmx::find_project_gmx(&input_path)? {
+                    config.files = gmx::gml_files(&project_gmx)?;
+                    config.dialect = Some(Dialect::Gms1);
+                    return Ok(config);
+                }
+
                 fn take_in_gml_files(directory_path: &PathBuf, config: &mut Config) {
                     let gml_name = OsStr::new("gml");
+                    let format_yy = config.print_flags.contains(PrintFlags::FORMAT_YY);
 
                     for entry in
                         fs::read_dir(directory_path).expect(&format!("Error reading directory {:?}.", directory_path))
@@ -34,7 +70,7 @@ impl Config {
                         let path = entry.path();
 
                         if path.is_dir() == false {
-                            if path.extension() == Some(gml_name) {
+                            if path.extension() == Some(gml_name) || (format_yy && yy::is_yy(&path)) {
                                 config.load_file_path(path);
                             }
                         } else {
@@ -50,6 +86,17 @@ impl Config {
                 config.load_file_path(input_path);
             }
 
+            (false, false) if input_path.extension() == Some(OsStr::new("yyp")) => {
+                config.cache = input_path.parent().map(|directory| directory.join(CACHE_FILE));
+                config.take_in_project(&input_path)?;
+            }
+
+            (false, false) if gmx::is_project_gmx(&input_path) => {
+                config.cache = input_path.parent().map(|directory| directory.join(CACHE_FILE));
+                config.files = gmx::gml_files(&input_path)?;
+                config.dialect = Some(Dialect::Gms1);
+            }
+
             (false, false) => {
                 return Err("Did not pass -f but gave a filepath. Pass -f for files.");
             }
@@ -61,12 +108,47 @@ impl Config {
     pub fn load_file_path(&mut self, path: PathBuf) {
         self.files.push(path);
     }
+
+    /// Keeps only the files git says have changed since `base` (or, without one, which aren't
+    /// committed yet), in the repository around `input_path`.
+    pub fn retain_changed(&mut self, input_path: &Path, base: Option<&str>) -> Result<(), String> {
+        let directory = if input_path.is_dir() {
+            input_path
+        } else {
+            input_path
+                .parent()
+                .filter(|parent| parent.as_os_str().is_empty() == false)
+                .unwrap_or_else(|| Path::new("."))
+        };
+
+        let changed: HashSet<PathBuf> = git::changed_files(directory, base)?
+            .into_iter()
+            .filter_map(|file| file.canonicalize().ok())
+            .collect();
+        self.files
+            .retain(|file| file.canonicalize().is_ok_and(|file| changed.contains(&file)));
+
+        Ok(())
+    }
+
+    /// Takes in the GML `yyp` owns, and with `FORMAT_YY`, the `.yyp` and its resources' `.yy` files.
+    fn take_in_project(&mut self, yyp: &Path) -> Result<(), &'static str> {
+        self.files = project::gml_files(yyp)?;
+        if self.print_flags.contains(PrintFlags::FORMAT_YY) {
+            self.files.push(yyp.to_path_buf());
+            self.files.extend(project::yy_files(yyp)?);
+        }
+
+        Ok(())
+    }
 }
 
 bitflags::bitflags! {
     pub struct PrintFlags: u8 {
-        const OVERWRITE = 0b001;
-        const LOGS      = 0b010;
-        const LOG_AST   = 0b100;
+        const OVERWRITE         = 0b0001;
+        const LOGS              = 0b0010;
+        const LOG_AST           = 0b0100;
+        const VERIFY_IDEMPOTENT = 0b1000;
+        const FORMAT_YY         = 0b1_0000;
     }
 }
@@ -0,0 +1,731 @@
+use super::ast::Ast;
+use super::diff;
+use super::expressions::{Expr, ExprBoxInterior};
+use super::lex_token::{Span, Token, TokenType};
+use super::line_endings;
+use super::scanner::{Dialect, Scanner};
+use super::statements::{Statement, StatementWrapper};
+use super::visitor::{self, Visitor};
+use serde::Serialize;
+use std::fmt;
+
+/// Everything between two tokens which doesn't change what the code means.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum TriviaKind {
+    /// A run of spaces and tabs (and any `\r` which isn't part of a line ending).
+    Whitespace,
+    /// A single `\n` or `\r\n`.
+    Newline,
+    Comment,
+    MultilineComment,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+/// A token, and the trivia around it. A token's trailing trivia runs up to (but not including)
+/// the end of its line; everything after that leads the next token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken<'a> {
+    pub token: Token<'a>,
+    pub text: &'a str,
+    pub leading_trivia: Vec<Trivia<'a>>,
+    pub trailing_trivia: Vec<Trivia<'a>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyntaxKind {
+    File,
+    /// The name of the `Statement` variant this node was parsed as.
+    Statement(&'static str),
+    /// The name of the `Expr` variant this node was parsed as.
+    Expr(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(SyntaxToken<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode<'a> {
+    pub kind: SyntaxKind,
+    pub span: Span,
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+impl<'a> SyntaxNode<'a> {
+    /// Every token under this node, in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken<'a>> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'b>(&'b self, tokens: &mut Vec<&'b SyntaxToken<'a>>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+/// A lossless concrete syntax tree: printing it gives back the exact bytes it was built from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst<'a> {
+    pub root: SyntaxNode<'a>,
+    /// The trivia after the last token in the file.
+    pub end_trivia: Vec<Trivia<'a>>,
+    has_bom: bool,
+}
+
+impl<'a> Cst<'a> {
+    /// Builds a flat tree of tokens straight from the source. This never fails, whatever the source.
+    pub fn parse(source: &'a str) -> Cst<'a> {
+        Cst::parse_with_dialect(source, Dialect::default())
+    }
+
+    /// The same as `parse`, for source in another dialect of GML.
+    pub fn parse_with_dialect(source: &'a str, dialect: Dialect) -> Cst<'a> {
+        let stripped = line_endings::strip_bom(source);
+        let (tokens, end_trivia) = lex(stripped, dialect);
+
+        Cst {
+            root: SyntaxNode {
+                kind: SyntaxKind::File,
+                span: Span::new(0, stripped.len()),
+                children: tokens.into_iter().map(SyntaxElement::Token).collect(),
+            },
+            end_trivia,
+            has_bom: stripped.len() != source.len(),
+        }
+    }
+
+    /// Builds a tree whose nodes are the statements and expressions of `ast`.
+    pub fn from_ast(ast: &Ast<'a>) -> Cst<'a> {
+        let source = ast.source();
+        let (tokens, end_trivia) = lex(source, ast.dialect());
+
+        let mut collector = NodeCollector::default();
+        visitor::walk_ast(&mut collector, ast);
+
+        let root = SyntaxNode {
+            kind: SyntaxKind::File,
+            span: Span::new(0, source.len()),
+            children: Vec::new(),
+        };
+
+        Cst {
+            root: nest(root, collector.nodes, tokens),
+            end_trivia,
+            has_bom: ast.has_bom(),
+        }
+    }
+
+    pub fn tokens(&self) -> Vec<&SyntaxToken<'a>> {
+        self.root.tokens()
+    }
+}
+
+impl<'a> fmt::Display for Cst<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.has_bom {
+            f.write_str("\u{feff}")?;
+        }
+        for token in self.tokens() {
+            write_trivia(f, &token.leading_trivia)?;
+            f.write_str(token.text)?;
+            write_trivia(f, &token.trailing_trivia)?;
+        }
+        write_trivia(f, &self.end_trivia)
+    }
+}
+
+fn write_trivia(f: &mut fmt::Formatter<'_>, trivia: &[Trivia<'_>]) -> fmt::Result {
+    for piece in trivia {
+        f.write_str(piece.text)?;
+    }
+    Ok(())
+}
+
+fn lex(source: &str, dialect: Dialect) -> (Vec<SyntaxToken<'_>>, Vec<Trivia<'_>>) {
+    let mut tokens: Vec<SyntaxToken> = Vec::new();
+    let mut pending: Vec<Trivia> = Vec::new();
+    let mut gap_start = 0;
+
+    for token in Scanner::new(source).with_dialect(dialect) {
+        let kind = match token.token_type {
+            // a Newline's span leaves out its indentation, so we lex those gaps ourselves.
+            TokenType::Newline(_) => continue,
+            TokenType::Comment(_) => Some(TriviaKind::Comment),
+            TokenType::MultilineComment(_) => Some(TriviaKind::MultilineComment),
+            _ => None,
+        };
+
+        lex_gap(source, gap_start, token.span.start, &mut pending);
+        gap_start = token.span.end;
+
+        let text = &source[token.span.start..token.span.end];
+        match kind {
+            Some(kind) => pending.push(Trivia {
+                kind,
+                text,
+                span: token.span,
+            }),
+            None => {
+                let leading_trivia = match tokens.last_mut() {
+                    Some(previous) => split_trailing(&mut pending, &mut previous.trailing_trivia),
+                    None => std::mem::take(&mut pending),
+                };
+                tokens.push(SyntaxToken {
+                    token,
+                    text,
+                    leading_trivia,
+                    trailing_trivia: Vec::new(),
+                });
+            }
+        }
+    }
+    lex_gap(source, gap_start, source.len(), &mut pending);
+
+    let end_trivia = match tokens.last_mut() {
+        Some(previous) => split_trailing(&mut pending, &mut previous.trailing_trivia),
+        None => pending,
+    };
+
+    (tokens, end_trivia)
+}
+
+/// Moves everything before the first newline in `pending` onto the previous token,
+/// and returns the rest.
+fn split_trailing<'a>(pending: &mut Vec<Trivia<'a>>, trailing: &mut Vec<Trivia<'a>>) -> Vec<Trivia<'a>> {
+    let first_newline = pending
+        .iter()
+        .position(|trivia| trivia.kind == TriviaKind::Newline)
+        .unwrap_or(pending.len());
+    let rest = pending.split_off(first_newline);
+    trailing.append(pending);
+    rest
+}
+
+fn lex_gap<'a>(source: &'a str, start: usize, end: usize, trivia: &mut Vec<Trivia<'a>>) {
+    let bytes = source.as_bytes();
+    let mut i = start;
+
+    while i < end {
+        let (kind, len) = match bytes[i] {
+            b'\n' => (TriviaKind::Newline, 1),
+            b'\r' if i + 1 < end && bytes[i + 1] == b'\n' => (TriviaKind::Newline, 2),
+            _ => {
+                let mut run_end = i + 1;
+                while run_end < end && bytes[run_end] != b'\n' && !bytes[run_end..end].starts_with(b"\r\n") {
+                    run_end += 1;
+                }
+                (TriviaKind::Whitespace, run_end - i)
+            }
+        };
+
+        trivia.push(Trivia {
+            kind,
+            text: &source[i..i + len],
+            span: Span::new(i, i + len),
+        });
+        i += len;
+    }
+}
+
+#[derive(Default)]
+struct NodeCollector {
+    nodes: Vec<(Span, SyntaxKind)>,
+}
+
+impl<'a> Visitor<'a> for NodeCollector {
+    fn visit_statement(&mut self, statement: &StatementWrapper<'a>) {
+        self.nodes.push((
+            statement.span,
+            SyntaxKind::Statement(statement_name(&statement.statement)),
+        ));
+        visitor::walk_statement(self, statement);
+    }
+
+    fn visit_expr(&mut self, expr: &ExprBoxInterior<'a>) {
+        self.nodes.push((expr.span, SyntaxKind::Expr(expr_name(&expr.expr))));
+        visitor::walk_expr(self, expr);
+    }
+}
+
+/// Nests `tokens` under the innermost node whose span they start in. `nodes` must be in
+/// the order a `Visitor` finds them, which puts parents before their children.
+fn nest<'a>(root: SyntaxNode<'a>, nodes: Vec<(Span, SyntaxKind)>, tokens: Vec<SyntaxToken<'a>>) -> SyntaxNode<'a> {
+    let mut stack = vec![root];
+    let mut nodes = nodes.into_iter().filter(|(span, _)| span.start < span.end).peekable();
+
+    for token in tokens {
+        let start = token.token.span.start;
+        while let Some((span, kind)) = nodes.next_if(|(span, _)| span.start <= start) {
+            close_nodes_before(&mut stack, span.start);
+            stack.push(SyntaxNode {
+                kind,
+                span,
+                children: Vec::new(),
+            });
+        }
+        close_nodes_before(&mut stack, start);
+
+        stack.last_mut().unwrap().children.push(SyntaxElement::Token(token));
+    }
+
+    close_nodes_before(&mut stack, usize::MAX);
+    stack.pop().unwrap()
+}
+
+fn close_nodes_before(stack: &mut Vec<SyntaxNode<'_>>, position: usize) {
+    while stack.len() > 1 && stack.last().unwrap().span.end <= position {
+        let node = stack.pop().unwrap();
+        // comments are trivia here, so the nodes the parser made for them have nothing left in them.
+        if !node.children.is_empty() {
+            stack.last_mut().unwrap().children.push(SyntaxElement::Node(node));
+        }
+    }
+}
+
+fn statement_name(statement: &Statement<'_>) -> &'static str {
+    match statement {
+        Statement::VariableDeclList { .. } => "VariableDeclList",
+        Statement::EnumDeclaration { .. } => "EnumDeclaration",
+        Statement::If { .. } => "If",
+        Statement::WhileWithRepeat { .. } => "WhileWithRepeat",
+        Statement::DoUntil { .. } => "DoUntil",
+        Statement::For { .. } => "For",
+        Statement::Switch { .. } => "Switch",
+        Statement::ExpresssionStatement { .. } => "ExpresssionStatement",
+        Statement::Block { .. } => "Block",
+        Statement::Return { .. } => "Return",
+        Statement::Break => "Break",
+        Statement::Exit => "Exit",
+        Statement::Comment { .. } => "Comment",
+        Statement::MultilineComment { .. } => "MultilineComment",
+        Statement::RegionBegin(_) => "RegionBegin",
+        Statement::RegionEnd(_) => "RegionEnd",
+        Statement::Macro(_) => "Macro",
+        Statement::Define { .. } => "Define",
+        Statement::Header { .. } => "Header",
+        Statement::Verbatim(_) => "Verbatim",
+    }
+}
+
+fn expr_name(expr: &Expr<'_>) -> &'static str {
+    match expr {
+        Expr::Call { .. } => "Call",
+        Expr::Binary { .. } => "Binary",
+        Expr::Grouping { .. } => "Grouping",
+        Expr::ArrayLiteral { .. } => "ArrayLiteral",
+        Expr::Literal { .. } => "Literal",
+        Expr::NumberStartDot { .. } => "NumberStartDot",
+        Expr::NumberEndDot { .. } => "NumberEndDot",
+        Expr::Unary { .. } => "Unary",
+        Expr::Postfix { .. } => "Postfix",
+        Expr::Assign { .. } => "Assign",
+        Expr::Identifier { .. } => "Identifier",
+        Expr::DotAccess { .. } => "DotAccess",
+        Expr::DataStructureAccess { .. } => "DataStructureAccess",
+        Expr::Ternary { .. } => "Ternary",
+        Expr::Newline => "Newline",
+        Expr::Comment { .. } => "Comment",
+        Expr::MultilineComment { .. } => "MultilineComment",
+        Expr::UnidentifiedAsLiteral { .. } => "UnidentifiedAsLiteral",
+    }
+}
+
+/// A comment from the source, and the token it belongs to.
+struct OwnedComment<'a> {
+    text: &'a str,
+    kind: TriviaKind,
+    span: Span,
+    /// The index of the token which owns this comment, or `None` if it comes after the last one.
+    owner: Option<usize>,
+    leading: bool,
+}
+
+fn comments<'a>(tokens: &[&SyntaxToken<'a>], end_trivia: &[Trivia<'a>]) -> Vec<OwnedComment<'a>> {
+    let is_comment = |trivia: &&Trivia| matches!(trivia.kind, TriviaKind::Comment | TriviaKind::MultilineComment);
+    let mut comments = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        for (trivia, leading) in token
+            .leading_trivia
+            .iter()
+            .map(|t| (t, true))
+            .chain(token.trailing_trivia.iter().map(|t| (t, false)))
+        {
+            if is_comment(&trivia) {
+                comments.push(OwnedComment {
+                    text: trivia.text,
+                    kind: trivia.kind,
+                    span: trivia.span,
+                    owner: Some(i),
+                    leading,
+                });
+            }
+        }
+    }
+    for trivia in end_trivia.iter().filter(is_comment) {
+        comments.push(OwnedComment {
+            text: trivia.text,
+            kind: trivia.kind,
+            span: trivia.span,
+            owner: None,
+            leading: true,
+        });
+    }
+
+    comments
+}
+
+/// Pairs up equal items of `old` and `new`: `result[i]` is where `old[i]` ended up in `new`, if anywhere.
+fn matching<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Option<usize>> {
+    let mut result = vec![None; old.len()];
+    let (mut old_index, mut new_index) = (0, 0);
+
+    for change in diff::diff(old, new).into_iter().chain(std::iter::once(diff::Change {
+        old_start: old.len(),
+        old_len: 0,
+        new_start: new.len(),
+        new_len: 0,
+    })) {
+        while old_index < change.old_start {
+            result[old_index] = Some(new_index);
+            old_index += 1;
+            new_index += 1;
+        }
+        old_index += change.old_len;
+        new_index += change.new_len;
+    }
+
+    result
+}
+
+/// Every token the Ast holds, in the order we visit them.
+#[derive(Default)]
+struct TokenCollector<'a> {
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Visitor<'a> for TokenCollector<'a> {
+    fn visit_token(&mut self, token: &Token<'a>) {
+        self.tokens.push(*token);
+    }
+}
+
+/// The Printer keeps comments by carrying them through the AST, but there are a few places
+/// the AST holds a comment which the Printer has no place for. This puts every comment of `ast`
+/// which didn't make it into `formatted` back where it was in the source, or, for one which
+/// wasn't in the source at all, in front of the token after it in `ast`. Comments are taken
+/// from `ast` as it is now, so ones rewritten or removed from it stay that way. It's a best
+/// effort, matching tokens up by type, so it doesn't promise anything on its own: that's
+/// `check_equivalence`'s job, which counts comments as tokens and refuses output missing any.
+pub(crate) fn restore_comments(ast: &Ast<'_>, formatted: &str) -> String {
+    let mut collector = TokenCollector::default();
+    visitor::walk_ast(&mut collector, ast);
+    let ast_tokens = collector.tokens;
+    let ast_comments: Vec<(usize, OwnedComment)> = ast_tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(i, token)| {
+            let (text, kind) = match token.token_type {
+                TokenType::Comment(text) => (text, TriviaKind::Comment),
+                TokenType::MultilineComment(text) => (text, TriviaKind::MultilineComment),
+                _ => return None,
+            };
+            // which token owns it is only worked out below, for the comments which were lost.
+            let comment = OwnedComment {
+                text,
+                kind,
+                span: token.span,
+                owner: None,
+                leading: true,
+            };
+            Some((i, comment))
+        })
+        .collect();
+    if ast_comments.is_empty() {
+        return formatted.to_owned();
+    }
+
+    let output = Cst::parse_with_dialect(formatted, ast.dialect());
+    let output_tokens = output.tokens();
+    let output_comments = comments(&output_tokens, &output.end_trivia);
+
+    let old_texts: Vec<&str> = ast_comments.iter().map(|(_, c)| c.text.trim_end()).collect();
+    let new_texts: Vec<&str> = output_comments.iter().map(|c| c.text.trim_end()).collect();
+    let mut lost: Vec<&(usize, OwnedComment)> = matching(&old_texts, &new_texts)
+        .into_iter()
+        .zip(&ast_comments)
+        .filter(|(found, _)| found.is_none())
+        .map(|(_, comment)| comment)
+        .collect();
+
+    // the Printer may have moved a comment past another one, or run it together with the
+    // code after it, instead of dropping it. We only put back as many copies as are missing,
+    // counting comments in the output which hold it, but not strings which happen to.
+    let mut missing: Vec<(&str, usize)> = Vec::new();
+    lost.retain(|(_, comment)| {
+        let text = comment.text.trim_end();
+        let index = match missing.iter().position(|(t, _)| *t == text) {
+            Some(index) => index,
+            None => {
+                let count = old_texts.iter().filter(|t| **t == text).count();
+                let found = new_texts.iter().filter(|t| t.contains(text)).count();
+                missing.push((text, count.saturating_sub(found)));
+                missing.len() - 1
+            }
+        };
+
+        let remaining = &mut missing[index].1;
+        if *remaining == 0 {
+            return false;
+        }
+        *remaining -= 1;
+        true
+    });
+    if lost.is_empty() {
+        return formatted.to_owned();
+    }
+
+    // only now do we need the source, to find the token each lost comment belonged to.
+    let (source_tokens, source_end_trivia) = lex(ast.source(), ast.dialect());
+    let source_tokens: Vec<&SyntaxToken> = source_tokens.iter().collect();
+    let source_comments = comments(&source_tokens, &source_end_trivia);
+    let source_token_at = |span: Span| {
+        source_tokens
+            .binary_search_by_key(&span.start, |t| t.token.span.start)
+            .ok()
+            .filter(|&i| source_tokens[i].token.span == span)
+    };
+
+    let source_types: Vec<TokenType> = source_tokens.iter().map(|t| t.token.token_type).collect();
+    let output_types: Vec<TokenType> = output_tokens.iter().map(|t| t.token.token_type).collect();
+    let token_map = matching(&source_types, &output_types);
+
+    // where each lost comment goes: before or after which output token, or at the end.
+    let mut before: Vec<Vec<&OwnedComment>> = vec![Vec::new(); output_tokens.len()];
+    let mut after: Vec<Vec<&OwnedComment>> = vec![Vec::new(); output_tokens.len()];
+    let mut at_end = Vec::new();
+
+    for (index, comment) in lost {
+        let (owner, leading) = match source_comments.iter().find(|c| c.span == comment.span) {
+            Some(source_comment) => (source_comment.owner, source_comment.leading),
+            None => {
+                // comments and newlines never line up with a token, so this is the next token.
+                let next = ast_tokens[index + 1..]
+                    .iter()
+                    .find_map(|token| source_token_at(token.span));
+                (next, true)
+            }
+        };
+
+        let owner = owner.and_then(|owner| {
+            let mapped = if leading {
+                token_map[owner..].iter().flatten().next()
+            } else {
+                token_map[..=owner].iter().rev().flatten().next()
+            };
+            mapped.copied()
+        });
+
+        match owner {
+            Some(owner) if leading => before[owner].push(comment),
+            Some(mut owner) => {
+                // a line comment has to end its line, or it would comment out the code after it.
+                if comment.kind == TriviaKind::Comment {
+                    while owner + 1 < output_tokens.len() && !ends_line(&output_tokens[owner + 1].leading_trivia) {
+                        owner += 1;
+                    }
+                }
+                after[owner].push(comment);
+            }
+            None => at_end.push(comment),
+        }
+    }
+
+    let mut restored = String::with_capacity(formatted.len() + 64);
+    if output.has_bom {
+        restored.push('\u{feff}');
+    }
+    for (i, token) in output_tokens.iter().enumerate() {
+        push_trivia(&mut restored, &token.leading_trivia);
+        for comment in &before[i] {
+            restored.push_str(comment.text);
+            if comment.kind == TriviaKind::Comment || ends_line(&token.leading_trivia) {
+                let line_start = restored.rfind('\n').map_or(0, |i| i + 1);
+                let indentation: String = restored[line_start..]
+                    .chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .collect();
+                restored.push('\n');
+                restored.push_str(&indentation);
+            } else {
+                restored.push(' ');
+            }
+        }
+
+        restored.push_str(token.text);
+        push_trivia(&mut restored, &token.trailing_trivia);
+        for comment in &after[i] {
+            restored.push(' ');
+            restored.push_str(comment.text);
+        }
+    }
+
+    push_trivia(&mut restored, &output.end_trivia);
+    for comment in at_end {
+        if !restored.is_empty() && !restored.ends_with('\n') {
+            restored.push('\n');
+        }
+        restored.push_str(comment.text);
+        restored.push('\n');
+    }
+
+    restored
+}
+
+fn ends_line(leading_trivia: &[Trivia<'_>]) -> bool {
+    leading_trivia.iter().any(|trivia| trivia.kind == TriviaKind::Newline)
+}
+
+fn push_trivia(output: &mut String, trivia: &[Trivia<'_>]) {
+    for piece in trivia {
+        output.push_str(piece.text);
+    }
+}
+
+#[cfg(test)]
+mod cst_test {
+    use super::*;
+    use crate::ast::{format_ast, parse};
+    use crate::lang_config::LangConfig;
+    use crate::visitor::{walk_ast_mut, VisitorMut};
+
+    #[test]
+    fn round_trip() {
+        let sources = [
+            "",
+            "\u{feff}x = 1;\r\n",
+            "  // only a comment\n\n",
+            "var a = 1, /* one */ b = 2; // two\r\n\tif (a) {\n  b(); \r c @ ;\n}\n/* end */",
+            "x = \"unterminated",
+        ];
+
+        for source in sources.iter() {
+            assert_eq!(&Cst::parse(source).to_string(), source);
+        }
+
+        let source = "\u{feff}if (a) {\n    b = c + 1; // c\n}\n";
+        assert_eq!(Cst::from_ast(&parse(source).unwrap()).to_string(), source);
+    }
+
+    #[test]
+    fn trivia_attachment() {
+        let cst = Cst::parse("a; // trailing\n// leading\nb;");
+        let tokens = cst.tokens();
+
+        assert_eq!(tokens[1].text, ";");
+        assert_eq!(
+            tokens[1].trailing_trivia.iter().map(|t| t.text).collect::<Vec<_>>(),
+            vec![" ", "// trailing"]
+        );
+        assert_eq!(tokens[2].text, "b");
+        assert_eq!(
+            tokens[2].leading_trivia.iter().map(|t| t.kind).collect::<Vec<_>>(),
+            vec![TriviaKind::Newline, TriviaKind::Comment, TriviaKind::Newline]
+        );
+    }
+
+    #[test]
+    fn tree() {
+        let cst = Cst::from_ast(&parse("x = f(1);").unwrap());
+
+        let statement = match &cst.root.children[0] {
+            SyntaxElement::Node(node) => node,
+            SyntaxElement::Token(_) => panic!("expected a statement"),
+        };
+        assert_eq!(statement.kind, SyntaxKind::Statement("ExpresssionStatement"));
+
+        let assign = match &statement.children[0] {
+            SyntaxElement::Node(node) => node,
+            SyntaxElement::Token(_) => panic!("expected an expression"),
+        };
+        assert_eq!(assign.kind, SyntaxKind::Expr("Assign"));
+        assert_eq!(
+            assign.tokens().iter().map(|t| t.text).collect::<Vec<_>>(),
+            vec!["x", "=", "f", "(", "1", ")"]
+        );
+        assert_eq!(statement.tokens().last().unwrap().text, ";");
+    }
+
+    #[test]
+    fn lost_comments_are_restored() {
+        let source = parse("switch (a) {\ndefault /* inline */:\n    // own line\n    x(); // after\n}\n").unwrap();
+
+        assert_eq!(
+            restore_comments(&source, "switch (a) {\n    default:\n        x();\n}\n"),
+            "switch (a) {\n    default /* inline */:\n        // own line\n        x(); // after\n}\n"
+        );
+        assert_eq!(
+            restore_comments(
+                &source,
+                "switch (a) {\n    default /* inline */:\n        // own line\n        x(); // after\n}\n"
+            ),
+            "switch (a) {\n    default /* inline */:\n        // own line\n        x(); // after\n}\n"
+        );
+
+        // a string holding the same text doesn't stand in for the comment.
+        let source = parse("switch (a) {\ndefault /* a */:\n    x = \"/* a */\";\n}\n").unwrap();
+        assert_eq!(
+            restore_comments(&source, "switch (a) {\n    default:\n        x = \"/* a */\";\n}\n"),
+            "switch (a) {\n    default /* a */:\n        x = \"/* a */\";\n}\n"
+        );
+    }
+
+    struct CommentRewriter(&'static str);
+
+    impl<'a> VisitorMut<'a> for CommentRewriter {
+        fn visit_token_mut(&mut self, token: &mut Token<'a>) {
+            if let TokenType::Comment(_) | TokenType::MultilineComment(_) = token.token_type {
+                token.token_type = TokenType::MultilineComment(self.0);
+            }
+        }
+    }
+
+    #[test]
+    fn comments_come_from_the_ast() {
+        let lang_config = LangConfig::default();
+
+        let mut ast = parse("x = 1; /* old note */\n").unwrap();
+        walk_ast_mut(&mut CommentRewriter("/* renamed */"), &mut ast);
+        assert_eq!(format_ast(&ast, &lang_config), "x = 1; /* renamed */\n");
+
+        // even a comment the Printer has no place for is put back as the AST has it now.
+        let mut ast = parse("switch (a) {\ndefault /* why */:\n    x();\n}\n").unwrap();
+        walk_ast_mut(&mut CommentRewriter("/* because */"), &mut ast);
+        assert_eq!(
+            format_ast(&ast, &lang_config),
+            "switch (a) {\n    default /* because */:\n        x();\n}\n"
+        );
+
+        let mut ast = parse("// header\nx = 1;\n// gone\ny = 2;\n").unwrap();
+        ast.statements.truncate(1);
+        assert_eq!(format_ast(&ast, &lang_config), "// header\n");
+
+        let mut ast = parse("y = 1;\nswitch (a) {\ndefault /* gone */:\n    x();\n}\n").unwrap();
+        ast.statements.truncate(1);
+        assert_eq!(format_ast(&ast, &lang_config), "y = 1;\n");
+    }
+}
@@ -0,0 +1,280 @@
+use super::error::FormatError;
+use super::lex_token::Span;
+use serde_json::json;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// How we report problems: as text for people, or as one JSON object per line for tools.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// The place in a file a diagnostic points at. Lines and columns are 0-based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+    /// Where the problem is in the code we parsed: its text as UTF-8, with any BOM left out.
+    pub span: Option<Span>,
+    source_line: String,
+    width: usize,
+}
+
+impl Position {
+    /// Points at `width` bytes from `line` and `column` of `source`.
+    pub fn new(source: &str, line: u32, column: u32, width: usize) -> Position {
+        let source_line = source.lines().nth(line as usize).unwrap_or_default();
+        let column = usize::min(column as usize, source_line.len());
+        let column = (0..=column)
+            .rev()
+            .find(|i| source_line.is_char_boundary(*i))
+            .unwrap_or(0);
+
+        Position {
+            line,
+            column: column as u32,
+            span: None,
+            source_line: source_line.trim_end_matches('\r').to_owned(),
+            width,
+        }
+    }
+}
+
+/// One problem with one file, and everything we know which might help to fix it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: PathBuf,
+    pub message: String,
+    pub position: Option<Position>,
+    pub hint: Option<String>,
+    /// Anything longer we have to show, like a diff, which is printed after everything else.
+    pub note: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, path: &Path, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            path: path.to_owned(),
+            message,
+            position: None,
+            hint: None,
+            note: None,
+        }
+    }
+
+    pub fn at(mut self, position: Position) -> Diagnostic {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn with_hint(mut self, hint: &str) -> Diagnostic {
+        self.hint = Some(hint.to_owned());
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.note = Some(note);
+        self
+    }
+
+    /// Describes a `FormatError` which came from formatting `source`.
+    pub fn from_format_error(path: &Path, source: &str, error: &FormatError) -> Diagnostic {
+        match error {
+            FormatError::UnexpectedToken {
+                found,
+                span,
+                line,
+                column,
+                expected,
+            } => {
+                let mut position = Position::new(source, *line, *column, span.end - span.start);
+                position.span = Some(*span);
+
+                Diagnostic::new(
+                    Severity::Error,
+                    path,
+                    format!("unexpected {}, expected {}", found, expected.join(" or ")),
+                )
+                .at(position)
+                .with_hint(
+                    "if this is syntax gml_fmt doesn't understand yet, `--recover` will leave \
+                     the statement as it is and format the rest of the file",
+                )
+            }
+            FormatError::UnexpectedEof {
+                span,
+                line,
+                column,
+                expected,
+            } => {
+                let mut position = Position::new(source, *line, *column, 1);
+                position.span = Some(*span);
+
+                Diagnostic::new(
+                    Severity::Error,
+                    path,
+                    format!("unexpected end of file, expected {}", expected.join(" or ")),
+                )
+                .at(position)
+                .with_hint("check for a bracket, parenthesis or string which was never closed")
+            }
+            FormatError::Io { source, .. } => Diagnostic::new(Severity::Error, path, source.to_string()),
+        }
+    }
+
+    pub fn emit(&self, message_format: MessageFormat) {
+        match message_format {
+            MessageFormat::Human => print!("{}", self.render()),
+            MessageFormat::Json => println!("{}", self.to_json()),
+        }
+    }
+
+    /// Renders this diagnostic with the line it points at, and a caret under the problem.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        writeln!(output, "{}: {}", self.severity.name(), self.message).unwrap();
+
+        match &self.position {
+            Some(position) => {
+                let line_number = (position.line + 1).to_string();
+                let gutter = " ".repeat(line_number.len());
+
+                writeln!(
+                    output,
+                    "{}--> {}:{}:{}",
+                    gutter,
+                    self.path.display(),
+                    position.line + 1,
+                    position.column + 1
+                )
+                .unwrap();
+                writeln!(output, "{} |", gutter).unwrap();
+                writeln!(output, "{} | {}", line_number, expand_tabs(&position.source_line)).unwrap();
+
+                let before = expand_tabs(&position.source_line[..position.column as usize]);
+                let rest = &position.source_line[position.column as usize..];
+                let underlined = rest
+                    .char_indices()
+                    .take_while(|(i, _)| *i < position.width)
+                    .count()
+                    .max(1);
+                writeln!(
+                    output,
+                    "{} | {}{}",
+                    gutter,
+                    " ".repeat(before.chars().count()),
+                    "^".repeat(underlined)
+                )
+                .unwrap();
+
+                if let Some(hint) = &self.hint {
+                    writeln!(output, "{} = hint: {}", gutter, hint).unwrap();
+                }
+            }
+            None => {
+                writeln!(output, " --> {}", self.path.display()).unwrap();
+                if let Some(hint) = &self.hint {
+                    writeln!(output, "  = hint: {}", hint).unwrap();
+                }
+            }
+        }
+
+        if let Some(note) = &self.note {
+            output.push_str(note);
+        }
+
+        output
+    }
+
+    /// Writes this diagnostic as a single line of JSON. Lines and columns are 1-based here,
+    /// to match what's printed for people. `byte_start` and `byte_end` are the `span`, so they
+    /// count into the decoded code rather than the file: a BOM isn't counted, and neither is the
+    /// XML around the code of a `.gmx` action.
+    pub fn to_json(&self) -> String {
+        let mut value = json!({
+            "severity": self.severity.name(),
+            "file": self.path.to_string_lossy(),
+            "message": self.message,
+            "hint": self.hint,
+            "note": self.note,
+        });
+
+        if let Some(position) = &self.position {
+            value["line"] = json!(position.line + 1);
+            value["column"] = json!(position.column + 1);
+            if let Some(span) = position.span {
+                value["byte_start"] = json!(span.start);
+                value["byte_end"] = json!(span.end);
+            }
+        }
+
+        value.to_string()
+    }
+}
+
+fn expand_tabs(line: &str) -> String {
+    line.replace('\t', "    ")
+}
+
+#[cfg(test)]
+mod diagnostics_test {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn render_unexpected_token() {
+        let source = "x = 1;\nswitch (a) {\n\t?? case 1: break;\n}\n";
+        let error = Parser::new(source).build_ast().unwrap_err();
+        let diagnostic = Diagnostic::from_format_error(Path::new("scr_test.gml"), source, &error);
+
+        assert_eq!(
+            diagnostic.render(),
+            "error: unexpected `?`, expected `case` or `default` or `}`
+ --> scr_test.gml:3:2
+  |
+3 |     ?? case 1: break;
+  |     ^
+  = hint: if this is syntax gml_fmt doesn't understand yet, `--recover` will leave the statement as it is and format the rest of the file
+"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let source = "var x = ";
+        let error = Parser::new(source).build_ast().unwrap_err();
+        let diagnostic = Diagnostic::from_format_error(Path::new("scr_test.gml"), source, &error);
+        let value: serde_json::Value = serde_json::from_str(&diagnostic.to_json()).unwrap();
+
+        assert_eq!(value["severity"], "error");
+        assert_eq!(value["message"], "unexpected end of file, expected an expression");
+        assert_eq!(value["line"], 1);
+        assert_eq!(value["column"], 9);
+        assert_eq!(value["byte_start"], 8);
+
+        let diagnostic = Diagnostic::new(Severity::Warning, Path::new("scr_test.gml"), "mixed".to_owned());
+        let value: serde_json::Value = serde_json::from_str(&diagnostic.to_json()).unwrap();
+        assert_eq!(value["hint"], serde_json::Value::Null);
+        assert!(value.get("line").is_none());
+    }
+}
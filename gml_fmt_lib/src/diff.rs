@@ -0,0 +1,335 @@
+use std::fmt::Write;
+
+/// Past this many edits into a search for the middle of a script, finding the shortest one
+/// stops being worth it, and we settle for splitting the sequences in half instead. The script
+/// might then be a little longer than it needs to be, but the time stays close to linear.
+const MAX_EDIT_DISTANCE: usize = 256;
+
+const CONTEXT_LINES: usize = 3;
+
+/// One run of differences: `old_len` items starting at `old_start` were replaced by
+/// `new_len` items starting at `new_start`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Change {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+/// Finds the changes which turn `old` into `new`, using Myers' algorithm.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Change> {
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut changes = Vec::new();
+    compare(old_middle, 0, new_middle, 0, &mut changes);
+
+    changes.retain(|change| change.old_len != 0 || change.new_len != 0);
+    for change in &mut changes {
+        change.old_start += prefix;
+        change.new_start += prefix;
+    }
+
+    changes
+}
+
+/// Pushes the changes which turn `old` into `new` onto `changes`, using Myers' algorithm in linear
+/// space: rather than remembering every step of the search to walk back through, we find the
+/// middle of the shortest script from both ends at once, and then solve the halves on either side
+/// of it the same way. `old` starts at `old_start` and `new` at `new_start` in the sequences we
+/// were first given.
+fn compare<T: PartialEq>(old: &[T], old_start: usize, new: &[T], new_start: usize, changes: &mut Vec<Change>) {
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    let (old_start, new_start) = (old_start + prefix, new_start + prefix);
+
+    if old.is_empty() || new.is_empty() {
+        push_change(
+            changes,
+            Change {
+                old_start,
+                old_len: old.len(),
+                new_start,
+                new_len: new.len(),
+            },
+        );
+        return;
+    }
+
+    let (x_start, y_start, x_end, y_end) = middle_snake(old, new);
+    compare(&old[..x_start], old_start, &new[..y_start], new_start, changes);
+    compare(
+        &old[x_end..],
+        old_start + x_end,
+        &new[y_end..],
+        new_start + y_end,
+        changes,
+    );
+}
+
+/// Where the middle snake (the run of matches in the middle of the shortest script) of two
+/// sequences, which differ at both ends, starts and ends. If it's too far in to be worth finding,
+/// we split both sequences in half.
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T]) -> (usize, usize, usize, usize) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+
+    // how far along each diagonal we've reached, from the start forwards and from the end backwards.
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+
+    for d in 0..=max {
+        // we can only get this far with more than `MAX_EDIT_DISTANCE` items, so both halves are smaller.
+        if d as usize > MAX_EDIT_DISTANCE {
+            let (x, y) = (old.len() / 2, new.len() / 2);
+            return (x, y, x, y);
+        }
+
+        let mut k = -d;
+        while k <= d {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && forward[index - 1] < forward[index + 1]) {
+                forward[index + 1]
+            } else {
+                forward[index - 1] + 1
+            };
+            let mut y = x - k;
+            let (x_start, y_start) = (x, y);
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index] = x;
+
+            // the backward search counts from the end, so its diagonal `delta - k` is this one.
+            let back_k = delta - k;
+            if odd && back_k > -d && back_k < d && x + backward[(back_k + offset) as usize] >= n {
+                return (x_start as usize, y_start as usize, x as usize, y as usize);
+            }
+            k += 2;
+        }
+
+        let mut k = -d;
+        while k <= d {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && backward[index - 1] < backward[index + 1]) {
+                backward[index + 1]
+            } else {
+                backward[index - 1] + 1
+            };
+            let mut y = x - k;
+            let (x_end, y_end) = (x, y);
+
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index] = x;
+
+            let forward_k = delta - k;
+            if odd == false && forward_k >= -d && forward_k <= d && x + forward[(forward_k + offset) as usize] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x_end) as usize,
+                    (m - y_end) as usize,
+                );
+            }
+            k += 2;
+        }
+    }
+
+    unreachable!("the forward and backward searches always meet by the middle")
+}
+
+/// Adds `change` to the end of `changes`, joining it onto the last one if they touch.
+fn push_change(changes: &mut Vec<Change>, change: Change) {
+    if let Some(last) = changes.last_mut() {
+        if last.old_start + last.old_len == change.old_start && last.new_start + last.new_len == change.new_start {
+            last.old_len += change.old_len;
+            last.new_len += change.new_len;
+            return;
+        }
+    }
+    changes.push(change);
+}
+
+/// Renders the difference between two texts as a unified diff.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let changes = diff(&old_lines, &new_lines);
+
+    let mut output = String::new();
+    if changes.is_empty() {
+        return output;
+    }
+    writeln!(output, "--- {}\n+++ {}", old_name, new_name).unwrap();
+
+    // group changes which are close enough to share their context.
+    let mut groups: Vec<Vec<Change>> = Vec::new();
+    for change in changes {
+        match groups.last_mut() {
+            Some(group)
+                if change.old_start <= group.last().map_or(0, |c| c.old_start + c.old_len) + 2 * CONTEXT_LINES =>
+            {
+                group.push(change)
+            }
+            _ => groups.push(vec![change]),
+        }
+    }
+
+    for group in groups {
+        let first = group[0];
+        let last = group[group.len() - 1];
+
+        let context_before = usize::min(CONTEXT_LINES, first.old_start);
+        let old_start = first.old_start - context_before;
+        let new_start = first.new_start - context_before;
+        let old_end = usize::min(old_lines.len(), last.old_start + last.old_len + CONTEXT_LINES);
+        let new_end = new_start + (old_end - old_start) + group.iter().map(|c| c.new_len).sum::<usize>()
+            - group.iter().map(|c| c.old_len).sum::<usize>();
+
+        writeln!(
+            output,
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_end - old_start,
+            new_start + 1,
+            new_end - new_start
+        )
+        .unwrap();
+
+        let mut line = old_start;
+        for change in &group {
+            for context in &old_lines[line..change.old_start] {
+                writeln!(output, " {}", context).unwrap();
+            }
+            for removed in &old_lines[change.old_start..change.old_start + change.old_len] {
+                writeln!(output, "-{}", removed).unwrap();
+            }
+            for added in &new_lines[change.new_start..change.new_start + change.new_len] {
+                writeln!(output, "+{}", added).unwrap();
+            }
+            line = change.old_start + change.old_len;
+        }
+        for context in &old_lines[line..old_end] {
+            writeln!(output, " {}", context).unwrap();
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod diff_test {
+    use super::*;
+
+    #[test]
+    fn simple_changes() {
+        let old: Vec<char> = "abcabba".chars().collect();
+        let new: Vec<char> = "cbabac".chars().collect();
+        let changes = diff(&old, &new);
+
+        // applying the changes in order should give us the new sequence back.
+        let mut rebuilt = Vec::new();
+        let mut pos = 0;
+        for change in &changes {
+            rebuilt.extend_from_slice(&old[pos..change.old_start]);
+            rebuilt.extend_from_slice(&new[change.new_start..change.new_start + change.new_len]);
+            pos = change.old_start + change.old_len;
+        }
+        rebuilt.extend_from_slice(&old[pos..]);
+        assert_eq!(rebuilt, new);
+
+        let edit_distance: usize = changes.iter().map(|c| c.old_len + c.new_len).sum();
+        assert_eq!(edit_distance, 5);
+    }
+
+    #[test]
+    fn shortest_scripts() {
+        // a tiny LCG, so that we try lots of pairs without pulling in a crate for it.
+        let mut seed = 7u32;
+        let mut random = |below: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % below
+        };
+
+        for _ in 0..500 {
+            let old: Vec<u32> = (0..random(12)).map(|_| random(3)).collect();
+            let new: Vec<u32> = (0..random(12)).map(|_| random(3)).collect();
+
+            let mut rebuilt = Vec::new();
+            let mut pos = 0;
+            let changes = diff(&old, &new);
+            for change in &changes {
+                rebuilt.extend_from_slice(&old[pos..change.old_start]);
+                rebuilt.extend_from_slice(&new[change.new_start..change.new_start + change.new_len]);
+                pos = change.old_start + change.old_len;
+            }
+            rebuilt.extend_from_slice(&old[pos..]);
+            assert_eq!(rebuilt, new);
+
+            // the shortest script keeps the longest common subsequence.
+            let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+            for i in 0..old.len() {
+                for j in 0..new.len() {
+                    lcs[i + 1][j + 1] = if old[i] == new[j] {
+                        lcs[i][j] + 1
+                    } else {
+                        usize::max(lcs[i][j + 1], lcs[i + 1][j])
+                    };
+                }
+            }
+            let edit_distance: usize = changes.iter().map(|c| c.old_len + c.new_len).sum();
+            assert_eq!(edit_distance, old.len() + new.len() - 2 * lcs[old.len()][new.len()]);
+        }
+    }
+
+    #[test]
+    fn unified() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nb\nc\nd\nE\nf\ng\nh\ni\nj\nk\n";
+
+        assert_eq!(
+            unified_diff(old, new, "first", "second"),
+            "--- first
++++ second
+@@ -2,9 +2,10 @@
+ b
+ c
+ d
+-e
++E
+ f
+ g
+ h
+ i
+ j
++k
+"
+        );
+        assert_eq!(unified_diff(old, old, "first", "second"), "");
+    }
+}
@@ -0,0 +1,337 @@
+use super::line_endings::LineEndings;
+use super::LangConfig;
+use std::fs;
+use std::path::Path;
+
+const EDITOR_CONFIG_NAME: &str = ".editorconfig";
+
+/// The name we match `.editorconfig` section globs against. Our `LangConfig` applies to a whole run,
+/// so we only care whether a section would match *a* `.gml` file, not any particular one.
+const GML_PROBE: &str = "script.gml";
+
+/// The subset of EditorConfig properties which apply to `.gml` files, collected from every
+/// `.editorconfig` between the input directory and the nearest `root = true`. A property which
+/// was set to `unset` keeps that value, so that files further up can't set it again, and
+/// `apply` treats it as though it was never set.
+#[derive(Debug, Default, PartialEq)]
+pub struct EditorConfig {
+    pub indent_style: Option<String>,
+    pub indent_size: Option<String>,
+    pub tab_width: Option<String>,
+    pub end_of_line: Option<String>,
+    pub insert_final_newline: Option<String>,
+    pub trim_trailing_whitespace: Option<String>,
+}
+
+impl EditorConfig {
+    /// Walks up from `directory`, reading each `.editorconfig`. Closer files take precedence,
+    /// and we stop once we find one which declares `root = true`.
+    pub fn find(directory: &Path) -> Option<EditorConfig> {
+        let mut editor_config = EditorConfig::default();
+        let mut found_any = false;
+
+        // a relative path like `.` has no parents of its own to walk up to.
+        let directory = directory.canonicalize().unwrap_or_else(|_| directory.to_path_buf());
+
+        for this_dir in directory.ancestors() {
+            let path = this_dir.join(EDITOR_CONFIG_NAME);
+            if path.is_file() == false {
+                continue;
+            }
+
+            if let Ok(contents) = fs::read_to_string(&path) {
+                found_any = true;
+                if editor_config.read_file(&contents) {
+                    break;
+                }
+            }
+        }
+
+        if found_any {
+            Some(editor_config)
+        } else {
+            None
+        }
+    }
+
+    /// Reads one `.editorconfig` file's worth of properties, only filling in properties which a
+    /// closer file hasn't already set. Returns whether the file was marked as `root`.
+    fn read_file(&mut self, contents: &str) -> bool {
+        let mut is_root = false;
+        let mut in_preamble = true;
+        let mut section_applies = false;
+        let mut this_file = EditorConfig::default();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                in_preamble = false;
+                section_applies = section_matches(&line[1..line.len() - 1]);
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(pos) => (
+                    line[..pos].trim().to_ascii_lowercase(),
+                    line[pos + 1..].trim().to_ascii_lowercase(),
+                ),
+                None => continue,
+            };
+
+            if in_preamble {
+                if key == "root" {
+                    is_root = value == "true";
+                }
+            } else if section_applies {
+                // later sections in the same file override earlier ones
+                this_file.set_property(&key, value);
+            }
+        }
+
+        self.inherit_from(this_file);
+        is_root
+    }
+
+    fn set_property(&mut self, key: &str, value: String) {
+        let slot = match key {
+            "indent_style" => &mut self.indent_style,
+            "indent_size" => &mut self.indent_size,
+            "tab_width" => &mut self.tab_width,
+            "end_of_line" => &mut self.end_of_line,
+            "insert_final_newline" => &mut self.insert_final_newline,
+            "trim_trailing_whitespace" => &mut self.trim_trailing_whitespace,
+            _ => return,
+        };
+
+        // `unset` replaces whatever an earlier section set, like any other value.
+        *slot = Some(value);
+    }
+
+    fn inherit_from(&mut self, further: EditorConfig) {
+        fn fill(ours: &mut Option<String>, theirs: Option<String>) {
+            if ours.is_none() {
+                *ours = theirs;
+            }
+        }
+
+        fill(&mut self.indent_style, further.indent_style);
+        fill(&mut self.indent_size, further.indent_size);
+        fill(&mut self.tab_width, further.tab_width);
+        fill(&mut self.end_of_line, further.end_of_line);
+        fill(&mut self.insert_final_newline, further.insert_final_newline);
+        fill(&mut self.trim_trailing_whitespace, further.trim_trailing_whitespace);
+    }
+
+    /// Maps these properties onto a `LangConfig`. Invalid values are ignored, as the
+    /// EditorConfig spec asks.
+    pub fn apply(&self, lang_config: &mut LangConfig) {
+        fn value(property: &Option<String>) -> Option<&str> {
+            property.as_deref().filter(|value| *value != "unset")
+        }
+
+        match value(&self.indent_style) {
+            Some("space") => lang_config.use_spaces = true,
+            Some("tab") => lang_config.use_spaces = false,
+            _ => {}
+        }
+
+        let tab_width = value(&self.tab_width).and_then(|w| w.parse::<usize>().ok());
+        let indent_size = match value(&self.indent_size) {
+            Some("tab") => tab_width,
+            Some(size) => size.parse::<usize>().ok(),
+            None => tab_width,
+        };
+        if let Some(indent_size) = indent_size {
+            lang_config.space_size = indent_size;
+        }
+
+        match value(&self.insert_final_newline) {
+            Some("true") => lang_config.newlines_at_end = usize::max(lang_config.newlines_at_end, 1),
+            Some("false") => lang_config.newlines_at_end = 0,
+            _ => {}
+        }
+
+        // we don't support old Mac style `cr` endings, so we leave those to `auto`.
+        match value(&self.end_of_line) {
+            Some("lf") => lang_config.line_endings = LineEndings::Lf,
+            Some("crlf") => lang_config.line_endings = LineEndings::Crlf,
+            _ => {}
+        }
+
+        // `trim_trailing_whitespace` has nothing to map onto, since we never leave trailing whitespace.
+    }
+}
+
+/// Checks if a section header like `*.gml` or `*.{gml,yy}` applies to `.gml` files. Sections
+/// which name a path (ie, contain a `/` after any leading `**/`) can't be decided for a
+/// whole run, so we skip them.
+fn section_matches(glob: &str) -> bool {
+    let glob = glob.trim_start_matches("**/");
+    let glob = glob.strip_prefix('/').unwrap_or(glob);
+    if glob.contains('/') {
+        return false;
+    }
+
+    let probe: Vec<char> = GML_PROBE.chars().collect();
+    expand_braces(glob)
+        .iter()
+        .any(|pattern| wildcard_match(&pattern.chars().collect::<Vec<_>>(), &probe))
+}
+
+/// Expands `{a,b}` alternatives into every pattern they describe.
+fn expand_braces(glob: &str) -> Vec<String> {
+    let open = match glob.find('{') {
+        Some(open) => open,
+        None => return vec![glob.to_owned()],
+    };
+
+    let close = match glob[open..].find('}') {
+        Some(close) => open + close,
+        None => return vec![glob.to_owned()],
+    };
+
+    let prefix = &glob[..open];
+    let suffix = &glob[close + 1..];
+    let mut output = Vec::new();
+
+    for alternative in glob[open + 1..close].split(',') {
+        for expanded in expand_braces(&format!("{}{}{}", prefix, alternative, suffix)) {
+            output.push(expanded);
+        }
+    }
+
+    output
+}
+
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => {
+            let rest = if pattern.get(1) == Some(&'*') {
+                &pattern[2..]
+            } else {
+                &pattern[1..]
+            };
+            (0..=name.len()).any(|skip| wildcard_match(rest, &name[skip..]))
+        }
+        Some('?') => name.is_empty() == false && wildcard_match(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let close = match pattern.iter().position(|c| *c == ']') {
+                Some(close) => close,
+                None => return name.first() == Some(&'[') && wildcard_match(&pattern[1..], &name[1..]),
+            };
+            let name_char = match name.first() {
+                Some(c) => *c,
+                None => return false,
+            };
+
+            let (negate, class) = match pattern.get(1) {
+                Some('!') => (true, &pattern[2..close]),
+                _ => (false, &pattern[1..close]),
+            };
+            if class.contains(&name_char) == negate {
+                return false;
+            }
+            wildcard_match(&pattern[close + 1..], &name[1..])
+        }
+        Some(c) => name.first() == Some(c) && wildcard_match(&pattern[1..], &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod editor_config_test {
+    use super::*;
+
+    #[test]
+    fn section_globs() {
+        assert!(section_matches("*"));
+        assert!(section_matches("*.gml"));
+        assert!(section_matches("**.gml"));
+        assert!(section_matches("**/*.gml"));
+        assert!(section_matches("*.{gml,yy}"));
+        assert!(section_matches("*.[gG]ml"));
+        assert!(section_matches("*.yy") == false);
+        assert!(section_matches("scripts/*.gml") == false);
+    }
+
+    #[test]
+    fn read_properties() {
+        let mut editor_config = EditorConfig::default();
+        let is_root = editor_config.read_file(
+            "root = true
+
+[*]
+indent_style = space
+indent_size = 2
+insert_final_newline = true
+
+[*.gml]
+indent_style = tab
+tab_width = 8
+
+[*.md]
+indent_size = 7
+",
+        );
+
+        assert!(is_root);
+        assert_eq!(editor_config.indent_style.as_deref(), Some("tab"));
+        assert_eq!(editor_config.indent_size.as_deref(), Some("2"));
+        assert_eq!(editor_config.tab_width.as_deref(), Some("8"));
+
+        let mut lang_config = LangConfig::default();
+        editor_config.apply(&mut lang_config);
+        assert!(lang_config.use_spaces == false);
+        assert_eq!(lang_config.space_size, 2);
+        assert_eq!(lang_config.newlines_at_end, 1);
+    }
+
+    #[test]
+    fn closer_files_win() {
+        let mut editor_config = EditorConfig::default();
+        editor_config.read_file("[*.gml]\nindent_size = 3\n");
+        editor_config.read_file("[*.gml]\nindent_size = 5\nindent_style = space\n");
+
+        assert_eq!(editor_config.indent_size.as_deref(), Some("3"));
+        assert_eq!(editor_config.indent_style.as_deref(), Some("space"));
+    }
+
+    #[test]
+    fn closer_files_unset() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_editor_config_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("outer/project/scripts")).unwrap();
+        fs::create_dir_all(directory.join("outer/other")).unwrap();
+        fs::write(directory.join(".editorconfig"), "root = true\n").unwrap();
+        fs::write(
+            directory.join("outer/.editorconfig"),
+            "[*]\nindent_size = 2\nend_of_line = crlf\ninsert_final_newline = false\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("outer/project/.editorconfig"),
+            "[*]\nend_of_line = lf\ninsert_final_newline = true\n[*.gml]\nend_of_line = unset\n",
+        )
+        .unwrap();
+        fs::write(directory.join("outer/other/.editorconfig"), "[*]\nindent_style = tab\n").unwrap();
+
+        // walking up the path as it's written would pass through `outer/other`, which isn't one
+        // of the project's parents.
+        let editor_config = EditorConfig::find(&directory.join("outer/other/../project/scripts")).unwrap();
+        assert_eq!(editor_config.indent_style, None);
+        assert_eq!(editor_config.indent_size.as_deref(), Some("2"));
+        assert_eq!(editor_config.end_of_line.as_deref(), Some("unset"));
+
+        let mut lang_config = LangConfig::default();
+        editor_config.apply(&mut lang_config);
+        assert_eq!(lang_config.space_size, 2);
+        assert_eq!(lang_config.line_endings, LangConfig::default().line_endings);
+        assert_eq!(lang_config.newlines_at_end, 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+use super::ast;
+use super::cst::{Cst, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, Trivia, TriviaKind};
+use super::error::FormatResult;
+use super::lang_config::LangConfig;
+use super::lex_token::{Span, Token, TokenType};
+use super::line_endings;
+use super::scanner::Scanner;
+use serde::Serialize;
+use std::path::Path;
+
+/// What to print instead of formatting, for tools which want the structure of a file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Emit {
+    /// The statements and expressions of each file, down to their tokens and trivia.
+    AstJson,
+    /// Every token the scanner finds in each file, comments and newlines included.
+    TokensJson,
+}
+
+// These mirror our own types, so that the schema in the README only changes when we mean it to.
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum JsonNode<'a> {
+    File {
+        span: Span,
+        children: Vec<JsonNode<'a>>,
+        /// The trivia after the last token, like a comment on the file's last line.
+        end_trivia: Vec<JsonTrivia<'a>>,
+    },
+    Statement {
+        #[serde(rename = "type")]
+        name: &'static str,
+        span: Span,
+        children: Vec<JsonNode<'a>>,
+    },
+    Expr {
+        #[serde(rename = "type")]
+        name: &'static str,
+        span: Span,
+        children: Vec<JsonNode<'a>>,
+    },
+    Token(JsonToken<'a>),
+}
+
+#[derive(Serialize)]
+struct JsonToken<'a> {
+    #[serde(rename = "type")]
+    name: String,
+    text: &'a str,
+    span: Span,
+    line: u32,
+    column: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leading_trivia: Option<Vec<JsonTrivia<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trailing_trivia: Option<Vec<JsonTrivia<'a>>>,
+}
+
+#[derive(Serialize)]
+struct JsonTrivia<'a> {
+    kind: TriviaKind,
+    text: &'a str,
+    span: Span,
+}
+
+#[derive(Serialize)]
+struct JsonFile<'a, T> {
+    file: &'a Path,
+    #[serde(flatten)]
+    contents: T,
+}
+
+#[derive(Serialize)]
+struct AstContents<'a> {
+    ast: JsonNode<'a>,
+}
+
+#[derive(Serialize)]
+struct TokensContents<'a> {
+    tokens: Vec<JsonToken<'a>>,
+}
+
+/// Describes `source` as a single line of JSON. Spans are byte offsets into the source
+/// without any UTF-8 BOM; lines and columns are 1-based.
+pub fn to_json(path: &Path, source: &str, emit: Emit, lang_config: &LangConfig) -> FormatResult<String> {
+    let json = match emit {
+        Emit::AstJson => {
+            let ast = ast::parse_with(source, lang_config)?;
+            let cst = Cst::from_ast(&ast);
+            let mut root = json_node(&cst.root);
+            if let JsonNode::File { end_trivia, .. } = &mut root {
+                *end_trivia = json_trivia(&cst.end_trivia);
+            }
+            serde_json::to_string(&JsonFile {
+                file: path,
+                contents: AstContents { ast: root },
+            })
+        }
+        Emit::TokensJson => {
+            let source = line_endings::strip_bom(source);
+            let tokens = Scanner::new(source)
+                .with_dialect(lang_config.dialect)
+                .map(|token| json_token(&token, &source[token.span.start..token.span.end]))
+                .collect();
+            serde_json::to_string(&JsonFile {
+                file: path,
+                contents: TokensContents { tokens },
+            })
+        }
+    };
+
+    Ok(json.expect("our JSON types always serialize"))
+}
+
+fn json_node<'a>(node: &SyntaxNode<'a>) -> JsonNode<'a> {
+    let children = node
+        .children
+        .iter()
+        .map(|child| match child {
+            SyntaxElement::Node(node) => json_node(node),
+            SyntaxElement::Token(token) => JsonNode::Token(json_syntax_token(token)),
+        })
+        .collect();
+
+    match node.kind {
+        SyntaxKind::File => JsonNode::File {
+            span: node.span,
+            children,
+            end_trivia: Vec::new(),
+        },
+        SyntaxKind::Statement(name) => JsonNode::Statement {
+            name,
+            span: node.span,
+            children,
+        },
+        SyntaxKind::Expr(name) => JsonNode::Expr {
+            name,
+            span: node.span,
+            children,
+        },
+    }
+}
+
+fn json_syntax_token<'a>(token: &SyntaxToken<'a>) -> JsonToken<'a> {
+    JsonToken {
+        leading_trivia: Some(json_trivia(&token.leading_trivia)),
+        trailing_trivia: Some(json_trivia(&token.trailing_trivia)),
+        ..json_token(&token.token, token.text)
+    }
+}
+
+fn json_trivia<'a>(trivia: &[Trivia<'a>]) -> Vec<JsonTrivia<'a>> {
+    trivia
+        .iter()
+        .map(|trivia| JsonTrivia {
+            kind: trivia.kind,
+            text: trivia.text,
+            span: trivia.span,
+        })
+        .collect()
+}
+
+fn json_token<'a>(token: &Token<'a>, text: &'a str) -> JsonToken<'a> {
+    JsonToken {
+        name: token_type_name(&token.token_type),
+        text,
+        span: token.span,
+        line: token.line_number + 1,
+        column: token.column_number + 1,
+        leading_trivia: None,
+        trailing_trivia: None,
+    }
+}
+
+/// The name of a `TokenType` variant, without whatever it holds.
+fn token_type_name(token_type: &TokenType<'_>) -> String {
+    let mut name = format!("{:?}", token_type);
+    if let Some(paren) = name.find('(') {
+        name.truncate(paren);
+    }
+    name
+}
+
+#[cfg(test)]
+mod emit_test {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn tokens_json() {
+        let json = to_json(
+            Path::new("scr_test.gml"),
+            "\u{feff}x = 1; // hi\n",
+            Emit::TokensJson,
+            &LangConfig::default(),
+        )
+        .unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["file"], "scr_test.gml");
+        assert_eq!(
+            value["tokens"][0],
+            json!({ "type": "Identifier", "text": "x", "span": { "start": 0, "end": 1 }, "line": 1, "column": 1 })
+        );
+        let types: Vec<&str> = value["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|token| token["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            vec!["Identifier", "Equal", "Number", "Semicolon", "Comment", "Newline"]
+        );
+    }
+
+    #[test]
+    fn ast_json() {
+        let json = to_json(
+            Path::new("scr_test.gml"),
+            "return a; // done\n",
+            Emit::AstJson,
+            &LangConfig::default(),
+        )
+        .unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+
+        let statement = &value["ast"]["children"][0];
+        assert_eq!(statement["kind"], "Statement");
+        assert_eq!(statement["type"], "Return");
+        assert_eq!(statement["span"], json!({ "start": 0, "end": 9 }));
+        assert_eq!(statement["children"][1]["kind"], "Expr");
+        assert_eq!(statement["children"][1]["type"], "Identifier");
+
+        let semicolon = &statement["children"][2];
+        assert_eq!(semicolon["kind"], "Token");
+        assert_eq!(semicolon["text"], ";");
+        assert_eq!(semicolon["trailing_trivia"][1]["kind"], "Comment");
+        assert_eq!(semicolon["trailing_trivia"][1]["text"], "// done");
+
+        assert!(to_json(
+            Path::new("scr_test.gml"),
+            "x = (",
+            Emit::AstJson,
+            &LangConfig::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn ast_json_round_trips() {
+        fn join(node: &Value, output: &mut String) {
+            let trivia = |list: &Value, output: &mut String| {
+                for trivia in list.as_array().into_iter().flatten() {
+                    output.push_str(trivia["text"].as_str().unwrap());
+                }
+            };
+
+            if node["kind"] == "Token" {
+                trivia(&node["leading_trivia"], output);
+                output.push_str(node["text"].as_str().unwrap());
+                trivia(&node["trailing_trivia"], output);
+            }
+            for child in node["children"].as_array().into_iter().flatten() {
+                join(child, output);
+            }
+            trivia(&node["end_trivia"], output);
+        }
+
+        for source in &[
+            "x = 1; // c\n\n// trailing\n",
+            "/* only a comment */\n",
+            "if (a) {\n\tb();\n}  \r\n",
+        ] {
+            let json = to_json(Path::new("scr_test.gml"), source, Emit::AstJson, &LangConfig::default()).unwrap();
+            let mut joined = String::new();
+            join(&serde_json::from_str::<Value>(&json).unwrap()["ast"], &mut joined);
+            assert_eq!(&joined, source);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// GMS1 saved scripts in the system's ANSI code page, which for most users was Windows-1252.
+/// It's a superset of Latin-1 for every printable character, so it covers both.
+const LEGACY_FALLBACK: &Encoding = WINDOWS_1252;
+
+/// A source file's text, along with the encoding we'll need to write it back in.
+pub struct DecodedSource {
+    pub text: String,
+    pub encoding: &'static Encoding,
+}
+
+/// Decodes a file's bytes. With no declared encoding (or `"auto"`), valid UTF-8 is read as UTF-8 and
+/// anything else as Windows-1252. Otherwise, the declared encoding must decode cleanly.
+pub fn decode(bytes: Vec<u8>, declared: Option<&str>) -> Result<DecodedSource, String> {
+    let declared = match declared {
+        None | Some("auto") => None,
+        Some(label) => Some(lookup(label)?),
+    };
+
+    let encoding = match declared {
+        Some(encoding) if encoding != UTF_8 => encoding,
+
+        // We keep the BOM in our text, so that we can put it back when we're done.
+        _ => {
+            return match String::from_utf8(bytes) {
+                Ok(text) => Ok(DecodedSource { text, encoding: UTF_8 }),
+                Err(e) => {
+                    if declared.is_some() {
+                        return Err(format!(
+                            "not valid UTF-8 (bad byte at offset {})",
+                            e.utf8_error().valid_up_to()
+                        ));
+                    }
+                    let bytes = e.into_bytes();
+                    let (text, _) = LEGACY_FALLBACK.decode_without_bom_handling(&bytes);
+
+                    Ok(DecodedSource {
+                        text: text.into_owned(),
+                        encoding: LEGACY_FALLBACK,
+                    })
+                }
+            };
+        }
+    };
+
+    match encoding.decode_without_bom_handling_and_without_replacement(&bytes) {
+        Some(text) => Ok(DecodedSource {
+            text: text.into_owned(),
+            encoding,
+        }),
+        None => Err(format!("not valid {}", encoding.name())),
+    }
+}
+
+/// Encodes formatted text back into the encoding it was read in.
+pub fn encode(text: &str, encoding: &'static Encoding) -> Result<Vec<u8>, String> {
+    if encoding == UTF_8 {
+        return Ok(text.as_bytes().to_vec());
+    }
+
+    let (bytes, _, had_unmappable) = encoding.encode(text);
+    if had_unmappable {
+        return Err(format!(
+            "contains characters which can't be written as {}",
+            encoding.name()
+        ));
+    }
+
+    Ok(bytes.into_owned())
+}
+
+fn lookup(label: &str) -> Result<&'static Encoding, String> {
+    match Encoding::for_label(label.as_bytes()) {
+        // encoding_rs can only decode UTF-16, and we need to write the file back out again.
+        Some(encoding) if encoding.output_encoding() == encoding => Ok(encoding),
+        Some(encoding) => Err(format!("{} is not supported as a source encoding", encoding.name())),
+        None => Err(format!("{:?} is not a known encoding", label)),
+    }
+}
+
+#[cfg(test)]
+mod encoding_test {
+    use super::*;
+
+    #[test]
+    fn auto_falls_back_to_windows_1252() {
+        let bytes = b"show_message(\"caf\xe9\");".to_vec();
+        let decoded = decode(bytes.clone(), None).unwrap();
+
+        assert_eq!(decoded.text, "show_message(\"caf\u{e9}\");");
+        assert_eq!(decoded.encoding, WINDOWS_1252);
+        assert_eq!(encode(&decoded.text, decoded.encoding).unwrap(), bytes);
+    }
+
+    #[test]
+    fn declared_encodings() {
+        assert!(decode(b"caf\xe9".to_vec(), Some("utf-8")).is_err());
+        assert!(decode(b"caf\xe9".to_vec(), Some("klingon")).is_err());
+        assert!(decode(b"caf\xe9".to_vec(), Some("utf-16le")).is_err());
+
+        let decoded = decode(b"caf\xe9".to_vec(), Some("latin1")).unwrap();
+        assert_eq!(decoded.text, "caf\u{e9}");
+        assert_eq!(decoded.encoding, WINDOWS_1252);
+    }
+}
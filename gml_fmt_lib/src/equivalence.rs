@@ -0,0 +1,246 @@
+use super::lex_token::{Token, TokenType};
+use super::printer::Printer;
+use super::scanner::{Dialect, Scanner};
+use std::fmt;
+
+/// The first place where formatted code stopped meaning the same thing as its source.
+/// Lines and columns are 1-based.
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub original: String,
+    pub original_line: u32,
+    pub original_column: u32,
+    pub formatted: String,
+    pub formatted_line: u32,
+    pub formatted_column: u32,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "formatting would change {} at {}:{} into {} at {}:{} of the output",
+            self.original,
+            self.original_line,
+            self.original_column,
+            self.formatted,
+            self.formatted_line,
+            self.formatted_column
+        )
+    }
+}
+
+/// Re-scans both the original and formatted source, and checks that they hold the same tokens.
+/// Newlines are ignored, and the Printer is allowed to add semicolons, and a pair of parentheses
+/// around the whole condition of a control statement, like `if x` becoming `if (x)`. The Printer also
+/// drops stray semicolons right before a `)` (like the one in `for (;; i++;)`), so those are
+/// ignored too. Anything else, like parentheses in the middle of an expression, or a semicolon
+/// going missing between two statements, changes what the code means.
+pub fn check_equivalence(original: &str, formatted: &str) -> Result<(), Divergence> {
+    check_equivalence_in(original, formatted, Dialect::default())
+}
+
+/// The same as `check_equivalence`, for source in another dialect of GML.
+pub fn check_equivalence_in(original: &str, formatted: &str, dialect: Dialect) -> Result<(), Divergence> {
+    let mut original_tokens = Scanner::new(original)
+        .with_dialect(dialect)
+        .filter(is_significant)
+        .peekable();
+    let mut formatted_tokens = Scanner::new(formatted)
+        .with_dialect(dialect)
+        .filter(is_significant)
+        .peekable();
+
+    // how many parentheses deep we are in the output, and the depths of the ones the Printer added.
+    let mut depth = 0;
+    let mut added_parentheses: Vec<usize> = Vec::new();
+    let mut previous_formatted = None;
+
+    loop {
+        let original_token = original_tokens.peek().copied();
+        let formatted_token = formatted_tokens.peek().copied();
+
+        match (original_token, formatted_token) {
+            (None, None) if added_parentheses.is_empty() => return Ok(()),
+            (Some(original_token), Some(formatted_token)) if same_token(&original_token, &formatted_token) => {
+                original_tokens.next();
+            }
+            (Some(original_token), _)
+                if original_token.token_type == TokenType::Semicolon
+                    && original_tokens.clone().nth(1).map(|token| token.token_type) == Some(TokenType::RightParen) =>
+            {
+                original_tokens.next();
+                continue;
+            }
+            (_, Some(formatted_token)) if formatted_token.token_type == TokenType::Semicolon => {}
+            (_, Some(formatted_token))
+                if formatted_token.token_type == TokenType::LeftParen
+                    && previous_formatted.is_some_and(is_control_word) =>
+            {
+                added_parentheses.push(depth);
+            }
+            // the condition has to end where the added parentheses do, or they've changed what it groups.
+            (_, Some(formatted_token))
+                if formatted_token.token_type == TokenType::RightParen
+                    && depth
+                        .checked_sub(1)
+                        .is_some_and(|outer| added_parentheses.last() == Some(&outer))
+                    && formatted_tokens
+                        .clone()
+                        .nth(1)
+                        .is_some_and(|next| continues_expression(next.token_type))
+                        == false =>
+            {
+                added_parentheses.pop();
+            }
+            (original_token, formatted_token) => {
+                let describe = |token: Option<Token>| match token {
+                    Some(token) => (
+                        format!("`{}`", Printer::get_token_name(&token.token_type)),
+                        token.line_number + 1,
+                        token.column_number + 1,
+                    ),
+                    None => ("the end of the file".to_owned(), 0, 0),
+                };
+                let (original, original_line, original_column) = describe(original_token);
+                let (formatted, formatted_line, formatted_column) = describe(formatted_token);
+
+                return Err(Divergence {
+                    original,
+                    original_line,
+                    original_column,
+                    formatted,
+                    formatted_line,
+                    formatted_column,
+                });
+            }
+        }
+
+        // every path which gets here steps past the output's token.
+        let formatted_token = formatted_tokens.next().unwrap();
+        match formatted_token.token_type {
+            TokenType::LeftParen => depth += 1,
+            TokenType::RightParen => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        previous_formatted = Some(formatted_token.token_type);
+    }
+}
+
+fn is_significant(token: &Token) -> bool {
+    !matches!(token.token_type, TokenType::Newline(_))
+}
+
+/// The words whose condition the Printer may wrap in parentheses.
+fn is_control_word(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::If | TokenType::While | TokenType::With | TokenType::Repeat | TokenType::Switch | TokenType::Until
+    )
+}
+
+/// Tokens which carry on the expression before them, rather than starting something new.
+fn continues_expression(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::LeftParen
+            | TokenType::LeftBracket
+            | TokenType::ListIndexer
+            | TokenType::MapIndexer
+            | TokenType::GridIndexer
+            | TokenType::ArrayIndexer
+            | TokenType::Dot
+            | TokenType::Hook
+            | TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::Mod
+            | TokenType::ModAlias
+            | TokenType::Div
+            | TokenType::LogicalAnd
+            | TokenType::LogicalOr
+            | TokenType::LogicalXor
+            | TokenType::AndAlias
+            | TokenType::OrAlias
+            | TokenType::XorAlias
+            | TokenType::BitAnd
+            | TokenType::BitOr
+            | TokenType::BitXor
+            | TokenType::BitLeft
+            | TokenType::BitRight
+            | TokenType::Equal
+            | TokenType::EqualEqual
+            | TokenType::BangEqual
+            | TokenType::LessThanGreaterThan
+            | TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual
+    )
+}
+
+fn same_token(original: &Token, formatted: &Token) -> bool {
+    match (original.token_type, formatted.token_type) {
+        // the Printer writes `.5` as `0.5`, and `5.` as `5.0`
+        (TokenType::NumberStartDot(original), TokenType::Number(formatted)) => {
+            formatted.strip_prefix('0') == Some(original)
+        }
+        (TokenType::NumberEndDot(original), TokenType::Number(formatted)) => {
+            formatted.strip_suffix('0') == Some(original)
+        }
+        (original, formatted) => original == formatted,
+    }
+}
+
+#[cfg(test)]
+mod equivalence_test {
+    use super::*;
+
+    #[test]
+    fn allowed_changes() {
+        assert_eq!(
+            check_equivalence("if x y = .5\n", "if (x) {\n    y = 0.5;\n}\n"),
+            Err(Divergence {
+                original: "`y`".to_owned(),
+                original_line: 1,
+                original_column: 6,
+                formatted: "`{`".to_owned(),
+                formatted_line: 1,
+                formatted_column: 8,
+            })
+        );
+        assert!(check_equivalence("if x y = .5\n", "if (x) y = 0.5;\n").is_ok());
+        assert!(check_equivalence("for (;; i++;) {}", "for (;; i++) {}\n").is_ok());
+        assert!(check_equivalence("while a && b\n", "while (a && b);\n").is_ok());
+    }
+
+    #[test]
+    fn changed_meanings() {
+        let divergence = check_equivalence("x = a + b * c;", "x = (a + b) * c;\n").unwrap_err();
+        assert_eq!(
+            (divergence.original, divergence.formatted),
+            ("`a`".to_owned(), "`(`".to_owned())
+        );
+        assert!(check_equivalence("x = a; y = b;", "x = a y = b;\n").is_err());
+        assert!(check_equivalence("if x y = 1;", "if (x y = 1;\n").is_err());
+        assert!(check_equivalence("a", ")").is_err());
+
+        // the added parentheses have to hold the whole condition.
+        assert!(check_equivalence("if a + b * c x = 1;", "if (a + b) * c x = 1;\n").is_err());
+        assert!(check_equivalence("while a || b && c {}", "while (a || b) && c {}\n").is_err());
+        assert!(check_equivalence("if a + b * c x = 1;", "if (a + b * c) x = 1;\n").is_ok());
+    }
+
+    #[test]
+    fn divergence() {
+        let divergence = check_equivalence("x = a + b;\ny = c;", "x = a + b;\ny = d;\n").unwrap_err();
+        assert_eq!(
+            divergence.to_string(),
+            "formatting would change `c` at 2:5 into `d` at 2:5 of the output"
+        );
+
+        let divergence = check_equivalence("x = 1; // note", "x = 1;\n").unwrap_err();
+        assert_eq!(divergence.formatted, "the end of the file");
+    }
+}
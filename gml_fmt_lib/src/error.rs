@@ -0,0 +1,149 @@
+use super::lex_token::{Span, Token, TokenType};
+use super::printer::Printer;
+use std::{error, fmt, io, path::PathBuf};
+
+pub type FormatResult<T> = Result<T, FormatError>;
+
+/// Everything which can stop us from formatting a file. Lines and columns are 0-based,
+/// like a `Token`'s, and spans are byte ranges into the source we were given.
+#[derive(Debug)]
+pub enum FormatError {
+    UnexpectedToken {
+        /// A description of the token, such as ``identifier `foo` `` or `` `?` ``.
+        found: String,
+        span: Span,
+        line: u32,
+        column: u32,
+        expected: Vec<&'static str>,
+    },
+    UnexpectedEof {
+        span: Span,
+        line: u32,
+        column: u32,
+        expected: Vec<&'static str>,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl FormatError {
+    pub(crate) fn unexpected_token(token: &Token<'_>, expected: Vec<&'static str>) -> FormatError {
+        FormatError::UnexpectedToken {
+            found: describe_token(&token.token_type),
+            span: token.span,
+            line: token.line_number,
+            column: token.column_number,
+            expected,
+        }
+    }
+
+    pub(crate) fn unexpected_eof(source: &str, expected: Vec<&'static str>) -> FormatError {
+        let line = source.matches('\n').count() as u32;
+        let column = (source.len() - source.rfind('\n').map_or(0, |i| i + 1)) as u32;
+
+        FormatError::UnexpectedEof {
+            span: Span::new(source.len(), source.len()),
+            line,
+            column,
+            expected,
+        }
+    }
+
+    /// Where in the source the problem is, if it's in the source at all.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            FormatError::UnexpectedToken { span, .. } | FormatError::UnexpectedEof { span, .. } => Some(*span),
+            FormatError::Io { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::UnexpectedToken {
+                found,
+                line,
+                column,
+                expected,
+                ..
+            } => write!(
+                f,
+                "unexpected {} at {}:{}, expected {}",
+                found,
+                line + 1,
+                column + 1,
+                expected.join(" or ")
+            ),
+            FormatError::UnexpectedEof { expected, .. } => {
+                write!(f, "unexpected end of file, expected {}", expected.join(" or "))
+            }
+            FormatError::Io { path, source } => write!(f, "{:?}: {}", path, source),
+        }
+    }
+}
+
+impl error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FormatError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Describes a token the way we'd talk about it in an error message.
+fn describe_token(token_type: &TokenType<'_>) -> String {
+    match token_type {
+        TokenType::Identifier(name) => format!("identifier `{}`", name),
+        TokenType::Number(number) | TokenType::NumberStartDot(number) | TokenType::NumberEndDot(number) => {
+            format!("number `{}`", number)
+        }
+        TokenType::String(_) => "string".to_owned(),
+        TokenType::Newline(_) => "newline".to_owned(),
+        TokenType::Comment(_) | TokenType::MultilineComment(_) => "comment".to_owned(),
+        TokenType::Macro(_) => "macro".to_owned(),
+        TokenType::Header(header) => format!("`{}`", header.split_whitespace().next().unwrap_or_default()),
+        TokenType::RegionBegin(_) | TokenType::RegionEnd(_) => "region".to_owned(),
+        other => format!("`{}`", Printer::get_token_name(other)),
+    }
+}
+
+#[cfg(test)]
+mod error_test {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn unexpected_token_span() {
+        let source = "x = 1;\nswitch (a) {\n    ?? case 1: break;\n}\n";
+        let error = Parser::new(source).build_ast().unwrap_err();
+
+        assert_eq!(error.span(), Some(Span::new(24, 25)));
+        assert_eq!(&source[24..25], "?");
+        assert_eq!(
+            error.to_string(),
+            "unexpected `?` at 3:5, expected `case` or `default` or `}`"
+        );
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let source = "x = 1;\ny = ";
+        match Parser::new(source).build_ast().unwrap_err() {
+            FormatError::UnexpectedEof {
+                span,
+                line,
+                column,
+                expected,
+            } => {
+                assert_eq!(span, Span::new(11, 11));
+                assert_eq!((line, column), (1, 4));
+                assert_eq!(expected, vec!["an expression"]);
+            }
+            e => panic!("expected an UnexpectedEof, got {:?}", e),
+        }
+    }
+}
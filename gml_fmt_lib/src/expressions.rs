@@ -5,12 +5,16 @@ pub type CommentsAndNewlines<'a> = Option<Vec<Token<'a>>>;
 pub type DSAccess<'a> = Vec<(CommentsAndNewlines<'a>, ExprBox<'a>)>;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct ExprBoxInterior<'a> {
     pub expr: Expr<'a>,
     pub trailing_comments: CommentsAndNewlines<'a>,
+    /// From the first token of this expression to its last, leaving out any trailing comments.
+    pub span: Span,
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Expr<'a> {
     Call {
         procedure_name: ExprBox<'a>,
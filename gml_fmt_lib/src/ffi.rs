@@ -0,0 +1,168 @@
+// The C ABI, for GameMaker extensions, GMEdit and anything else which can load a shared library
+// but can't call Rust. `include/gml_fmt.h` declares everything here.
+
+use super::lang_config::LangConfig;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::{slice, str};
+
+pub const GML_FMT_OK: c_int = 0;
+/// A pointer we needed was null, or the source had a NUL byte in it.
+pub const GML_FMT_INVALID_ARGUMENT: c_int = 1;
+/// The source or the config wasn't UTF-8.
+pub const GML_FMT_INVALID_UTF8: c_int = 2;
+/// The config wasn't a `gml_fmt.toml` we could read.
+pub const GML_FMT_INVALID_CONFIG: c_int = 3;
+/// The source couldn't be parsed, so it wasn't formatted.
+pub const GML_FMT_PARSE_ERROR: c_int = 4;
+/// gml_fmt hit a bug. Please report it along with the source!
+pub const GML_FMT_INTERNAL_ERROR: c_int = 5;
+
+/// Formats the `len` bytes of UTF-8 at `src`, with `config_toml` (the contents of a `gml_fmt.toml`,
+/// or null for the defaults).
+///
+/// On success, returns `GML_FMT_OK` and points `*output` at the formatted code. Otherwise, returns
+/// one of the other `GML_FMT_` codes and points `*output` at a message saying what went wrong.
+/// Either way, `*output` is NUL-terminated, `*output_len` (if it isn't null) is its length without
+/// the NUL, and it must be given back to `gml_fmt_free`.
+///
+/// # Safety
+/// `src` must point to `len` readable bytes, `config_toml` must be null or NUL-terminated,
+/// and `output` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn gml_fmt_format(
+    src: *const c_char,
+    len: usize,
+    config_toml: *const c_char,
+    output: *mut *mut c_char,
+    output_len: *mut usize,
+) -> c_int {
+    if output.is_null() {
+        return GML_FMT_INVALID_ARGUMENT;
+    }
+
+    let (code, text) = match panic::catch_unwind(AssertUnwindSafe(|| format(src, len, config_toml))) {
+        Ok(Ok(formatted)) => (GML_FMT_OK, formatted),
+        Ok(Err(error)) => error,
+        Err(_) => (GML_FMT_INTERNAL_ERROR, "gml_fmt panicked while formatting".to_owned()),
+    };
+
+    // we've already turned away sources with NUL bytes in them, so neither of these can have any.
+    let text = CString::new(text).unwrap_or_default();
+    if !output_len.is_null() {
+        *output_len = text.as_bytes().len();
+    }
+    *output = text.into_raw();
+
+    code
+}
+
+/// Frees a string which `gml_fmt_format` gave back. Does nothing with null.
+///
+/// # Safety
+/// `text` must be null, or have come from `gml_fmt_format` and not been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn gml_fmt_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+unsafe fn format(src: *const c_char, len: usize, config_toml: *const c_char) -> Result<String, (c_int, String)> {
+    let source = if len == 0 {
+        &[]
+    } else if src.is_null() {
+        return Err((GML_FMT_INVALID_ARGUMENT, "src was null".to_owned()));
+    } else {
+        slice::from_raw_parts(src as *const u8, len)
+    };
+    if source.contains(&0) {
+        return Err((GML_FMT_INVALID_ARGUMENT, "src has a NUL byte in it".to_owned()));
+    }
+    let source = str::from_utf8(source).map_err(|e| (GML_FMT_INVALID_UTF8, format!("src is not UTF-8: {}", e)))?;
+
+    let lang_config = if config_toml.is_null() {
+        LangConfig::default()
+    } else {
+        let config_toml = CStr::from_ptr(config_toml)
+            .to_str()
+            .map_err(|e| (GML_FMT_INVALID_UTF8, format!("config_toml is not UTF-8: {}", e)))?;
+        LangConfig::from_toml(config_toml).map_err(|e| {
+            (
+                GML_FMT_INVALID_CONFIG,
+                format!("config_toml is not a valid config: {}", e),
+            )
+        })?
+    };
+
+    super::run(source, &lang_config, None).map_err(|e| (GML_FMT_PARSE_ERROR, e.to_string()))
+}
+
+#[cfg(test)]
+mod ffi_test {
+    use super::*;
+    use std::ptr;
+
+    unsafe fn call(source: &str, config_toml: Option<&str>) -> (c_int, String) {
+        let config_toml = config_toml.map(|config_toml| CString::new(config_toml).unwrap());
+        let mut output = ptr::null_mut();
+        let mut output_len = 0;
+
+        let code = gml_fmt_format(
+            source.as_ptr() as *const c_char,
+            source.len(),
+            config_toml
+                .as_ref()
+                .map_or(ptr::null(), |config_toml| config_toml.as_ptr()),
+            &mut output,
+            &mut output_len,
+        );
+        let text = CStr::from_ptr(output).to_str().unwrap().to_owned();
+        assert_eq!(text.len(), output_len);
+        gml_fmt_free(output);
+
+        (code, text)
+    }
+
+    #[test]
+    fn formats() {
+        unsafe {
+            assert_eq!(call("x=1", None), (GML_FMT_OK, "x = 1;\n".to_owned()));
+            assert_eq!(
+                call("if (a) {\nx=1;\ny=2;\n}", Some("use_spaces = false")),
+                (GML_FMT_OK, "if (a) {\n\tx = 1;\n\ty = 2;\n}\n".to_owned())
+            );
+            assert_eq!(call("", None).0, GML_FMT_OK);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        unsafe {
+            let (code, message) = call("x = (", None);
+            assert_eq!(code, GML_FMT_PARSE_ERROR);
+            assert!(message.starts_with("unexpected end of file"), "{}", message);
+
+            assert_eq!(call("x = 1;", Some("space_size = \"four\"")).0, GML_FMT_INVALID_CONFIG);
+            assert_eq!(call("x = 1;", Some("not toml")).0, GML_FMT_INVALID_CONFIG);
+            assert_eq!(call("x\0 = 1;", None).0, GML_FMT_INVALID_ARGUMENT);
+            assert_eq!(
+                gml_fmt_format(ptr::null(), 1, ptr::null(), ptr::null_mut(), ptr::null_mut()),
+                GML_FMT_INVALID_ARGUMENT
+            );
+
+            let mut output = ptr::null_mut();
+            let code = gml_fmt_format(
+                b"\xff".as_ptr() as *const c_char,
+                1,
+                ptr::null(),
+                &mut output,
+                ptr::null_mut(),
+            );
+            assert_eq!(code, GML_FMT_INVALID_UTF8);
+            gml_fmt_free(output);
+            gml_fmt_free(ptr::null_mut());
+        }
+    }
+}
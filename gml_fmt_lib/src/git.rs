@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Every file in the repository around `directory` which differs from `base`: committed since
+/// it, staged, modified in the working tree, or untracked (but not ignored). Without a `base`,
+/// that's everything not yet committed. With one, like `main`, we compare against where the
+/// current branch left it, so that commits made on `main` since don't count.
+pub(crate) fn changed_files(directory: &Path, base: Option<&str>) -> Result<Vec<PathBuf>, String> {
+    let root = PathBuf::from(git(directory, &["rev-parse", "--show-toplevel"])?.trim_end());
+
+    let base = match base {
+        Some(base) => Some(git(directory, &["merge-base", base, "HEAD"])?.trim_end().to_owned()),
+        // a repository with no commits yet has nothing to compare to, so every file is new.
+        None => git(directory, &["rev-parse", "--verify", "--quiet", "HEAD"])
+            .ok()
+            .map(|_| "HEAD".to_owned()),
+    };
+
+    let mut listed = match &base {
+        Some(base) => git(&root, &["diff", "--name-only", "-z", "--diff-filter=ACMR", base, "--"])?,
+        None => git(&root, &["ls-files", "--cached", "-z"])?,
+    };
+    listed.push_str(&git(&root, &["ls-files", "--others", "--exclude-standard", "-z"])?);
+
+    Ok(listed
+        .split('\0')
+        .filter(|file| file.is_empty() == false)
+        .map(|file| root.join(file))
+        .collect())
+}
+
+/// Runs `git` in `directory`, and gives back what it printed, or what it complained about.
+fn git(directory: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod git_test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn finds_changed_files() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_git_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("scripts")).unwrap();
+        let commit = |message: &str| {
+            git(&directory, &["add", "-A"]).unwrap();
+            git(
+                &directory,
+                &[
+                    "-c",
+                    "user.name=a",
+                    "-c",
+                    "user.email=a@a",
+                    "commit",
+                    "-q",
+                    "-m",
+                    message,
+                ],
+            )
+            .unwrap();
+        };
+        let changed = |base| {
+            let mut files: Vec<_> = changed_files(&directory.join("scripts"), base)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.strip_prefix(&directory).unwrap().to_path_buf())
+                .collect();
+            files.sort();
+            files
+        };
+
+        git(&directory, &["init", "-q", "-b", "main"]).unwrap();
+        fs::write(directory.join("scripts/a.gml"), "a = 1;\n").unwrap();
+        fs::write(directory.join(".gitignore"), "ignored.gml\n").unwrap();
+        fs::write(directory.join("ignored.gml"), "c = 1;\n").unwrap();
+        assert_eq!(
+            changed(None),
+            vec![PathBuf::from(".gitignore"), PathBuf::from("scripts/a.gml")]
+        );
+
+        commit("first");
+        assert!(changed(None).is_empty());
+
+        git(&directory, &["checkout", "-q", "-b", "feature"]).unwrap();
+        fs::write(directory.join("scripts/b.gml"), "b = 1;\n").unwrap();
+        commit("second");
+        fs::write(directory.join("scripts/a.gml"), "a = 2;\n").unwrap();
+        assert_eq!(changed(None), vec![PathBuf::from("scripts/a.gml")]);
+        assert_eq!(
+            changed(Some("main")),
+            vec![PathBuf::from("scripts/a.gml"), PathBuf::from("scripts/b.gml")]
+        );
+        assert!(changed_files(&directory, Some("no-such-branch")).is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+use super::diagnostics::{Diagnostic, Position, Severity};
+use super::equivalence::check_equivalence_in;
+use super::lang_config::LangConfig;
+use super::lex_token::Span;
+use super::project::{resource_path, EVENT_NAMES};
+use super::scanner::Dialect;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The `.project.gmx` at the top of `directory`, if it holds a GameMaker: Studio 1.4 project.
+pub(crate) fn find_project_gmx(directory: &Path) -> Result<Option<PathBuf>, &'static str> {
+    let mut found = None;
+
+    for entry in fs::read_dir(directory).map_err(|_| "Error reading directory.")? {
+        let path = entry.map_err(|_| "Error reading file")?.path();
+        if path.is_file() && is_project_gmx(&path) {
+            if found.is_some() {
+                return Err("Found more than one .project.gmx in the directory. Pass the one to format.");
+            }
+            found = Some(path);
+        }
+    }
+
+    Ok(found)
+}
+
+pub(crate) fn is_project_gmx(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".project.gmx")
+}
+
+/// Whether `path` is an object or timeline, whose code lives in its XML.
+pub(crate) fn is_gmx(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.ends_with(".object.gmx") || path.ends_with(".timeline.gmx")
+}
+
+/// Every script, object and timeline the project lists. Scripts are `.gml` files we format
+/// like any other, and objects and timelines are `.gmx` files for `format_xml`.
+pub(crate) fn gml_files(project_gmx: &Path) -> Result<Vec<PathBuf>, &'static str> {
+    let project_directory = project_gmx.parent().unwrap_or_else(|| Path::new(""));
+    let xml = fs::read_to_string(project_gmx).map_err(|_| "Error reading the .project.gmx file.")?;
+
+    let scripts =
+        elements(&xml, "script").map(|span| project_directory.join(resource_path(&xml[span.start..span.end])));
+    let objects = elements(&xml, "object")
+        .map(|span| project_directory.join(resource_path(&format!("{}.object.gmx", &xml[span.start..span.end]))));
+    let timelines = elements(&xml, "timeline")
+        .map(|span| project_directory.join(resource_path(&format!("{}.timeline.gmx", &xml[span.start..span.end]))));
+
+    Ok(scripts
+        .chain(objects)
+        .chain(timelines)
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+/// One "Execute code" action, with the byte range of its (still escaped) code in the XML.
+#[derive(Debug, PartialEq)]
+struct CodeAction {
+    span: Span,
+    code: String,
+    /// Which event or moment it's in, like `Step_0` or `moment 10`.
+    event: String,
+}
+
+/// Formats the code of every "Execute code" action in an object or timeline, and writes it
+/// back into the XML, leaving every other byte alone. Actions we can't format are left as
+/// they are, and described in the diagnostics. Their code is always read as GMS1's GML,
+/// whatever dialect `lang_config` is set to.
+pub(crate) fn format_xml(path: &Path, xml: &str, lang_config: &LangConfig) -> (String, Vec<Diagnostic>) {
+    let lang_config = &LangConfig {
+        dialect: Dialect::Gms1,
+        ..lang_config.clone()
+    };
+    let mut output = String::with_capacity(xml.len());
+    let mut diagnostics = Vec::new();
+    let mut position = 0;
+
+    for action in code_actions(xml) {
+        if action.code.trim().is_empty() || action.code.contains("// @gml_fmt ignore") {
+            continue;
+        }
+        let note = format!("in the code action of {} in {}", action.event, path.display());
+
+        let mut formatted = match super::run(&action.code, lang_config, None) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                diagnostics.push(Diagnostic::from_format_error(path, &action.code, &e).with_note(note));
+                continue;
+            }
+        };
+        // GameMaker doesn't end code actions with a newline, so we don't add one.
+        if action.code.ends_with('\n') == false {
+            formatted.truncate(formatted.trim_end_matches(['\r', '\n']).len());
+        }
+        if formatted == action.code {
+            continue;
+        }
+
+        if let Err(divergence) = check_equivalence_in(&action.code, &formatted, Dialect::Gms1) {
+            let mut diagnostic =
+                Diagnostic::new(Severity::Error, path, format!("refusing to write file: {}", divergence))
+                    .with_hint("this is a bug in gml_fmt, so please report it along with this file")
+                    .with_note(note);
+            if divergence.original_line != 0 {
+                diagnostic = diagnostic.at(Position::new(
+                    &action.code,
+                    divergence.original_line - 1,
+                    divergence.original_column - 1,
+                    1,
+                ));
+            }
+            diagnostics.push(diagnostic);
+            continue;
+        }
+
+        output.push_str(&xml[position..action.span.start]);
+        output.push_str(&escape(&formatted));
+        position = action.span.end;
+    }
+    output.push_str(&xml[position..]);
+
+    (output, diagnostics)
+}
+
+/// Finds the actions with library 1's action 603, "Execute code", whose only argument is the code.
+fn code_actions(xml: &str) -> Vec<CodeAction> {
+    let mut actions = Vec::new();
+
+    for action in elements(xml, "action") {
+        let inner = &xml[action.start..action.end];
+        let is_code = |tag, value| elements(inner, tag).next().map(|span| &inner[span.start..span.end]) == Some(value);
+        if is_code("libid", "1") == false || is_code("id", "603") == false {
+            continue;
+        }
+
+        if let Some(string) = elements(inner, "string").next() {
+            let span = Span::new(action.start + string.start, action.start + string.end);
+            actions.push(CodeAction {
+                span,
+                code: unescape(&xml[span.start..span.end]),
+                event: event_name(&xml[..action.start]),
+            });
+        }
+    }
+
+    actions
+}
+
+/// Names the event (or, in a timeline, the moment) which the XML before an action ends inside.
+fn event_name(before: &str) -> String {
+    let attribute = |tag: &str, name: &str| {
+        let tag = &tag[tag.find(&format!("{}=\"", name))? + name.len() + 2..];
+        Some(tag[..tag.find('"')?].to_owned())
+    };
+
+    let event = before.rfind("<event ").map(|start| &before[start..]);
+    let step = before.rfind("<step>").map(|start| &before[start + "<step>".len()..]);
+
+    match (event, step) {
+        (Some(event), _) => {
+            let event_type = attribute(event, "eventtype").and_then(|event_type| event_type.parse::<usize>().ok());
+            let name = event_type
+                .and_then(|event_type| EVENT_NAMES.get(event_type))
+                .unwrap_or(&"an");
+            // collisions say which object, rather than a number.
+            let number = attribute(event, "enumb")
+                .or_else(|| attribute(event, "ename"))
+                .unwrap_or_default();
+            format!("the {}_{} event", name, number)
+        }
+        (None, Some(step)) => format!("moment {}", &step[..step.find('<').unwrap_or(0)]),
+        (None, None) => "an event".to_owned(),
+    }
+}
+
+/// The byte range of the contents of every `<tag>...</tag>` in `xml`. Elements with attributes
+/// are skipped, which is what we want: `<scripts name="scripts">` is a folder, not a script.
+fn elements<'a>(xml: &'a str, tag: &str) -> impl Iterator<Item = Span> + 'a {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut position = 0;
+
+    std::iter::from_fn(move || {
+        let start = position + xml[position..].find(&open)? + open.len();
+        let end = start + xml[start..].find(&close)?;
+        position = end + close.len();
+        Some(Span::new(start, end))
+    })
+}
+
+fn unescape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(ampersand) = rest.find('&') {
+        output.push_str(&rest[..ampersand]);
+        rest = &rest[ampersand..];
+
+        let entity = rest.find(';').map(|semicolon| &rest[1..semicolon]);
+        let character = match entity {
+            Some("lt") => Some('<'),
+            Some("gt") => Some('>'),
+            Some("amp") => Some('&'),
+            Some("quot") => Some('"'),
+            Some("apos") => Some('\''),
+            Some(entity) if entity.starts_with("#x") => {
+                u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+            }
+            Some(entity) if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+
+        match (character, entity) {
+            (Some(character), Some(entity)) => {
+                output.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Escapes code the way GameMaker does, which leaves quotes alone.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod gmx_test {
+    use super::*;
+
+    const OBJECT: &str =
+        "<!--This Document is generated by GameMaker, if you edit it by hand then you do so at your own risk!-->\r
+<object>\r
+  <events>\r
+    <event eventtype=\"0\" enumb=\"0\">\r
+      <action>\r
+        <libid>1</libid>\r
+        <id>603</id>\r
+        <kind>7</kind>\r
+        <arguments>\r
+          <argument>\r
+            <kind>1</kind>\r
+            <string>if(a&lt;b){\r
+x=\"&amp;\"\r
+y=2\r
+}</string>\r
+          </argument>\r
+        </arguments>\r
+      </action>\r
+      <action>\r
+        <libid>1</libid>\r
+        <id>203</id>\r
+        <kind>0</kind>\r
+        <arguments/>\r
+      </action>\r
+    </event>\r
+    <event eventtype=\"3\" enumb=\"0\">\r
+      <action>\r
+        <libid>1</libid>\r
+        <id>603</id>\r
+        <arguments>\r
+          <argument>\r
+            <kind>1</kind>\r
+            <string>x = (</string>\r
+          </argument>\r
+        </arguments>\r
+      </action>\r
+    </event>\r
+  </events>\r
+</object>\r
+";
+
+    #[test]
+    fn finds_code_actions() {
+        let actions = code_actions(OBJECT);
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].code, "if(a<b){\r\nx=\"&\"\r\ny=2\r\n}");
+        assert_eq!(actions[0].event, "the Create_0 event");
+        assert_eq!(actions[1].code, "x = (");
+        assert_eq!(actions[1].event, "the Step_0 event");
+    }
+
+    #[test]
+    fn formats_code_actions() {
+        let (xml, diagnostics) = format_xml(Path::new("obj_a.object.gmx"), OBJECT, &LangConfig::default());
+
+        assert_eq!(
+            xml,
+            OBJECT.replace(
+                "if(a&lt;b){\r\nx=\"&amp;\"\r\ny=2\r\n}",
+                "if (a &lt; b) {\r\n    x = \"&amp;\";\r\n    y = 2;\r\n}"
+            )
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].note.as_deref(),
+            Some("in the code action of the Step_0 event in obj_a.object.gmx")
+        );
+    }
+
+    #[test]
+    fn gms1_strings() {
+        let object = "<object><events><event eventtype=\"0\" enumb=\"0\"><action><libid>1</libid><id>603</id>\
+                      <arguments><argument><string>path=\"C:\\\"+'a\nb'</string></argument></arguments>\
+                      </action></event></events></object>";
+        let (xml, diagnostics) = format_xml(Path::new("obj_a.object.gmx"), object, &LangConfig::default());
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(xml, object.replace("path=\"C:\\\"+'a\nb'", "path = \"C:\\\" + 'a\nb';"));
+    }
+
+    #[test]
+    fn timelines_and_projects() {
+        let timeline = "<timeline><entry><step>10</step><event><action><libid>1</libid><id>603</id>\
+                        <arguments><argument><string>x=1</string></argument></arguments></action></event></entry></timeline>";
+        assert_eq!(code_actions(timeline)[0].event, "moment 10");
+        assert!(is_gmx(Path::new("objects/obj_a.object.gmx")));
+        assert!(is_gmx(Path::new("timelines/tl_a.timeline.gmx")));
+        assert!(is_gmx(Path::new("rooms/rm_a.room.gmx")) == false);
+        assert!(is_gmx(Path::new("game.project.gmx")) == false);
+
+        let project = "<assets><scripts name=\"scripts\"><script>scripts\\scr_a.gml</script></scripts>\
+                       <objects name=\"objects\"><object>objects\\obj_a</object></objects></assets>";
+        let found: Vec<&str> = elements(project, "script")
+            .chain(elements(project, "object"))
+            .map(|span| &project[span.start..span.end])
+            .collect();
+        assert_eq!(found, vec!["scripts\\scr_a.gml", "objects\\obj_a"]);
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(unescape("a &lt;&gt; &#65;&#x42; &amp;amp; & b"), "a <> AB &amp; & b");
+        assert_eq!(escape("a < b && c > d"), "a &lt; b &amp;&amp; c &gt; d");
+    }
+}
@@ -0,0 +1,206 @@
+use super::lex_token::TokenType;
+use super::scanner::Scanner;
+use super::LangConfig;
+use fnv::FnvHashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Statistics about how an existing codebase is written, gathered by scanning its `.gml` files.
+/// `gml_fmt init` uses these to pick the `LangConfig` which will change the least code.
+#[derive(Debug, Default)]
+pub struct CodebaseStats {
+    pub files: usize,
+    pub tab_indented_lines: usize,
+    pub space_indented_lines: usize,
+    /// How often each width appears as the step between one line's indentation and the next.
+    pub indent_steps: FnvHashMap<usize, usize>,
+    pub braces_same_line: usize,
+    pub braces_next_line: usize,
+    /// How many files end with each number of trailing newlines.
+    pub trailing_newlines: FnvHashMap<usize, usize>,
+    pub double_quoted_strings: usize,
+    pub single_quoted_strings: usize,
+}
+
+impl CodebaseStats {
+    pub fn gather(files: &[PathBuf]) -> CodebaseStats {
+        let mut stats = CodebaseStats::default();
+
+        for this_file in files {
+            // we're only taking a survey here, so files we can't read just don't get a vote.
+            if let Ok(contents) = fs::read_to_string(this_file) {
+                stats.add_source(&contents);
+            }
+        }
+
+        stats
+    }
+
+    pub fn add_source(&mut self, source: &str) {
+        self.files += 1;
+
+        let lines: Vec<&str> = source.lines().collect();
+        let mut last_line = None;
+        let mut last_indent = 0;
+        let mut prev_was_newline = true;
+
+        for token in Scanner::new(source) {
+            match token.token_type {
+                TokenType::Newline(_) => {
+                    prev_was_newline = true;
+                    continue;
+                }
+
+                TokenType::LeftBrace => {
+                    if prev_was_newline {
+                        self.braces_next_line += 1;
+                    } else {
+                        self.braces_same_line += 1;
+                    }
+                }
+
+                TokenType::String(literal) => {
+                    if literal.trim_start_matches('@').starts_with('\'') {
+                        self.single_quoted_strings += 1;
+                    } else {
+                        self.double_quoted_strings += 1;
+                    }
+                }
+
+                _ => {}
+            }
+            prev_was_newline = false;
+
+            // only the first token on each line tells us about indentation. Lines within
+            // multiline comments and strings never start with a token, so they're skipped.
+            let line_number = token.line_number as usize;
+            if last_line == Some(line_number) {
+                continue;
+            }
+            last_line = Some(line_number);
+
+            let line = match lines.get(line_number) {
+                Some(line) => line,
+                None => continue,
+            };
+            let whitespace: String = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+
+            let indent = if whitespace.starts_with('\t') {
+                self.tab_indented_lines += 1;
+                None
+            } else if whitespace.is_empty() == false {
+                self.space_indented_lines += 1;
+                Some(whitespace.len())
+            } else {
+                Some(0)
+            };
+
+            if let Some(indent) = indent {
+                if indent > last_indent {
+                    *self.indent_steps.entry(indent - last_indent).or_insert(0) += 1;
+                }
+                last_indent = indent;
+            }
+        }
+
+        let code_end = source.trim_end_matches(char::is_whitespace).len();
+        let trailing_newlines = source[code_end..].matches('\n').count();
+        *self.trailing_newlines.entry(trailing_newlines).or_insert(0) += 1;
+    }
+
+    /// The `LangConfig` which best matches what we've seen. Anything we didn't see enough of
+    /// to have an opinion on is left at its default.
+    pub fn best_lang_config(&self) -> LangConfig {
+        let mut lang_config = LangConfig::default();
+
+        if self.tab_indented_lines + self.space_indented_lines != 0 {
+            lang_config.use_spaces = self.space_indented_lines >= self.tab_indented_lines;
+        }
+
+        if let Some(step) = most_common(&self.indent_steps) {
+            lang_config.space_size = step;
+        }
+
+        if let Some(newlines) = most_common(&self.trailing_newlines) {
+            lang_config.newlines_at_end = newlines;
+        }
+
+        lang_config
+    }
+}
+
+fn most_common(counts: &FnvHashMap<usize, usize>) -> Option<usize> {
+    // ties go to the smaller value, so that we're deterministic.
+    counts
+        .iter()
+        .max_by(|(a_value, a_count), (b_value, b_count)| a_count.cmp(b_count).then(b_value.cmp(a_value)))
+        .map(|(value, _)| *value)
+}
+
+fn percentage(part: usize, other: usize) -> usize {
+    (part * 100).checked_div(part + other).unwrap_or(0)
+}
+
+impl fmt::Display for CodebaseStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Scanned {} files.", self.files)?;
+        writeln!(
+            f,
+            "Indentation: {}% spaces, {}% tabs.",
+            percentage(self.space_indented_lines, self.tab_indented_lines),
+            percentage(self.tab_indented_lines, self.space_indented_lines)
+        )?;
+        if let Some(step) = most_common(&self.indent_steps) {
+            writeln!(f, "Most common indent width: {}.", step)?;
+        }
+        writeln!(
+            f,
+            "Braces: {}% on the same line, {}% on the next line.",
+            percentage(self.braces_same_line, self.braces_next_line),
+            percentage(self.braces_next_line, self.braces_same_line)
+        )?;
+        if let Some(newlines) = most_common(&self.trailing_newlines) {
+            writeln!(f, "Most files end with {} newline(s).", newlines)?;
+        }
+        write!(
+            f,
+            "Strings: {}% double quoted, {}% single quoted.",
+            percentage(self.double_quoted_strings, self.single_quoted_strings),
+            percentage(self.single_quoted_strings, self.double_quoted_strings)
+        )
+    }
+}
+
+#[cfg(test)]
+mod inference_test {
+    use super::*;
+
+    #[test]
+    fn infer_two_space_allman() {
+        let mut stats = CodebaseStats::default();
+        stats.add_source(
+            "if (a)
+{
+  b = \"x\";
+  /* a comment
+        with its own indentation
+  */
+  while (c)
+  {
+    d = 'y';
+  }
+}
+",
+        );
+
+        assert_eq!(stats.braces_next_line, 2);
+        assert_eq!(stats.braces_same_line, 0);
+        assert_eq!(stats.tab_indented_lines, 0);
+
+        let lang_config = stats.best_lang_config();
+        assert!(lang_config.use_spaces);
+        assert_eq!(lang_config.space_size, 2);
+        assert_eq!(lang_config.newlines_at_end, 1);
+    }
+}
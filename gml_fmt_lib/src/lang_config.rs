@@ -1,9 +1,15 @@
+use super::editor_config::EditorConfig;
+use super::line_endings::LineEndings;
+use super::scanner::Dialect;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::Path;
 use std::{ffi::OsStr, fs};
 use toml;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// What our config file can be called, in the order we look for them.
+const CONFIG_NAMES: [&str; 3] = ["gml_fmt.toml", ".gml_fmt.toml", ".gml_fmt"];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LangConfig {
     #[serde(default = "use_spaces")]
     pub use_spaces: bool,
@@ -11,6 +17,18 @@ pub struct LangConfig {
     pub space_size: usize,
     #[serde(default = "newlines_at_end")]
     pub newlines_at_end: usize,
+    #[serde(default)]
+    pub line_endings: LineEndings,
+    /// The encoding source files are saved in, such as `windows-1252`. When this is `None`
+    /// (or `"auto"`), we read UTF-8 and fall back to Windows-1252 for anything which isn't.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Leaves statements we can't parse exactly as they were, instead of refusing to format the file.
+    #[serde(default)]
+    pub recover_from_errors: bool,
+    /// Which GameMaker's GML the code is written in. Projects with a `.project.gmx` are always `Gms1`.
+    #[serde(default)]
+    pub dialect: Dialect,
 }
 
 fn use_spaces() -> bool {
@@ -31,36 +49,108 @@ impl Default for LangConfig {
             use_spaces: true,
             space_size: 4,
             newlines_at_end: 1,
+            line_endings: LineEndings::Auto,
+            encoding: None,
+            recover_from_errors: false,
+            dialect: Dialect::Gms2,
         }
     }
 }
 
 impl LangConfig {
-    pub fn new(input_path: &PathBuf) -> LangConfig {
-        let names = vec![
-            OsStr::new("gml_fmt.toml"),
-            OsStr::new(".gml_fmt.toml"),
-            OsStr::new(".gml_fmt"),
-        ];
-
-        for entry in fs::read_dir(input_path).expect(&format!("Error reading directory {:?}", input_path)) {
-            let entry = entry.expect(&format!("Error reading file"));
-            let path = entry.path();
-
-            if path.is_file() {
-                let fname = path.file_name().expect("Error reading filename.");
-
-                if names.contains(&fname) {
-                    let lang_config: LangConfig = toml::from_str(&fs::read_to_string(path).unwrap()).unwrap();
-                    return lang_config;
-                }
+    /// Builds our config from the defaults, then any `.editorconfig` properties which apply to
+    /// `.gml` files, and finally our own `gml_fmt.toml`, which always takes precedence. Panics if
+    /// the directory or the `gml_fmt.toml` can't be read. Use `load` to handle that instead.
+    pub fn new(input_path: &Path) -> LangConfig {
+        LangConfig::load(input_path).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `new`, but describes what went wrong when the directory or the `gml_fmt.toml` in it
+    /// can't be read, rather than panicking.
+    pub fn load(input_path: &Path) -> Result<LangConfig, String> {
+        let mut lang_config = LangConfig::default();
+
+        if let Some(editor_config) = EditorConfig::find(input_path) {
+            editor_config.apply(&mut lang_config);
+        }
+
+        let entries =
+            fs::read_dir(input_path).map_err(|e| format!("Error reading directory {:?}: {}", input_path, e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| format!("Error reading file: {}", e))?.path();
+
+            if path.is_file()
+                && path
+                    .file_name()
+                    .is_some_and(|fname| CONFIG_NAMES.iter().any(|name| OsStr::new(name) == fname))
+            {
+                return lang_config.overridden_by_file(&path);
             }
         }
 
-        LangConfig {
-            newlines_at_end: 1,
-            use_spaces: true,
-            space_size: 4,
+        Ok(lang_config)
+    }
+
+    /// The config for a file in `directory`, for tools which format one file at a time from deep
+    /// inside a project, like editors. The `.editorconfig` files are found from `directory` like in
+    /// `load`, but the `gml_fmt.toml` comes from the nearest directory which has one, going up
+    /// from `directory` as far as `root` (or all the way, without one).
+    pub fn find(directory: &Path, root: Option<&Path>) -> Result<LangConfig, String> {
+        let mut lang_config = LangConfig::default();
+
+        if let Some(editor_config) = EditorConfig::find(directory) {
+            editor_config.apply(&mut lang_config);
+        }
+
+        let directory = directory.canonicalize().unwrap_or_else(|_| directory.to_path_buf());
+        let root = root.map(|root| root.canonicalize().unwrap_or_else(|_| root.to_path_buf()));
+        for this_dir in directory.ancestors() {
+            let path = CONFIG_NAMES
+                .iter()
+                .map(|name| this_dir.join(name))
+                .find(|path| path.is_file());
+            if let Some(path) = path {
+                return lang_config.overridden_by_file(&path);
+            }
+            if root.as_deref() == Some(this_dir) {
+                break;
+            }
+        }
+
+        Ok(lang_config)
+    }
+
+    /// Builds our config from the defaults and the contents of a `gml_fmt.toml`, for when
+    /// there's no directory to read it from.
+    pub fn from_toml(toml: &str) -> Result<LangConfig, toml::de::Error> {
+        LangConfig::default().overridden_by(toml::from_str(toml)?)
+    }
+
+    /// Writes this config out in the same format `gml_fmt.toml` is read in.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+
+    fn overridden_by_file(self, path: &Path) -> Result<LangConfig, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Error reading {:?}: {}", path, e))?;
+        let user_config: toml::Value =
+            toml::from_str(&contents).map_err(|e| format!("Error reading {:?}: {}", path, e))?;
+
+        self.overridden_by(user_config)
+            .map_err(|e| format!("Error reading {:?}: {}", path, e))
+    }
+
+    /// Lays every key the user wrote in their `gml_fmt.toml` over this config, leaving
+    /// everything else alone.
+    fn overridden_by(self, user_config: toml::Value) -> Result<LangConfig, toml::de::Error> {
+        let mut merged = toml::Value::try_from(&self).unwrap();
+
+        if let (Some(merged), toml::Value::Table(user_config)) = (merged.as_table_mut(), user_config) {
+            for (key, value) in user_config {
+                merged.insert(key, value);
+            }
         }
+
+        merged.try_into()
     }
 }
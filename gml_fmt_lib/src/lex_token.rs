@@ -1,4 +1,7 @@
+use serde::Serialize;
+
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[non_exhaustive]
 pub enum TokenType<'a> {
     LeftParen,
     RightParen,
@@ -60,6 +63,8 @@ pub enum TokenType<'a> {
     RegionBegin(&'a str),
     RegionEnd(&'a str),
     Define,
+    /// A GMEdit `#event`, `#section` or `#target` line, which starts the next part of a combined file.
+    Header(&'a str),
 
     Var,
     GlobalVar,
@@ -100,11 +105,27 @@ pub enum TokenType<'a> {
     UnidentifiedInput(&'a str),
 }
 
+/// A byte range into the source, from `start` up to (but not including) `end`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Token<'a> {
     pub token_type: TokenType<'a>,
     pub line_number: u32,
     pub column_number: u32,
+    /// Where this token is in the source. A `Newline`'s span is just its `\n`, and leaves out
+    /// the indentation it counts.
+    pub span: Span,
 }
 
 impl<'a> Token<'a> {
@@ -113,6 +134,7 @@ impl<'a> Token<'a> {
             token_type,
             line_number,
             column_number,
+            span: Span::default(),
         }
     }
 }
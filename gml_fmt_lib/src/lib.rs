@@ -1,28 +1,157 @@
 #![allow(clippy::bool_comparison)]
 
+mod ast;
+mod cache;
 mod config;
+mod cst;
+mod diagnostics;
+mod diff;
+mod editor_config;
+mod emit;
+mod encoding;
+mod equivalence;
+mod error;
 mod expressions;
+mod ffi;
+mod git;
+mod gmx;
+mod inference;
 mod lang_config;
 mod lex_token;
+mod line_endings;
 mod parser;
 mod printer;
+mod project;
 mod scanner;
 mod statements;
+mod text_edit;
+mod visitor;
+mod yy;
 
 use anyhow::Result as AnyResult;
-use parser::Parser;
-use printer::Printer;
+use cache::Cache;
+use line_endings::SourceLayout;
 use std::fs;
+use std::path::Path;
 
-pub use config::{Config, PrintFlags};
+pub use ast::{format_ast, parse, parse_with, parse_with_recovery, Ast};
+
+pub use config::{Config, PrintFlags, CACHE_FILE};
+pub use cst::{Cst, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, Trivia, TriviaKind};
+pub use diagnostics::{Diagnostic, MessageFormat, Position, Severity};
+pub use emit::Emit;
+pub use equivalence::{check_equivalence, check_equivalence_in, Divergence};
+pub use error::{FormatError, FormatResult};
+pub use expressions::{CommentsAndNewlines, DSAccess, Expr, ExprBox, ExprBoxInterior};
+pub use inference::CodebaseStats;
 pub use lang_config::LangConfig;
+pub use lex_token::{Span, Token, TokenType};
+pub use line_endings::LineEndings;
+pub use scanner::Dialect;
+pub use statements::{
+    Case, CaseType, DelimitedLine, DelimitedLines, Statement, StatementWrapper, StmtBox, VariableDecl,
+};
+pub use text_edit::{apply_edits, edits_between, format_to_edits, format_with_cursors, map_offset, TextEdit};
+pub use visitor::{
+    walk_ast, walk_ast_mut, walk_comments, walk_comments_mut, walk_expr, walk_expr_mut, walk_statement,
+    walk_statement_mut, Visitor, VisitorMut,
+};
 
 pub fn run_with_config(config: &Config, lang_config: &LangConfig) -> AnyResult<()> {
+    let lang_config = &LangConfig {
+        dialect: config.dialect.unwrap_or(lang_config.dialect),
+        ..lang_config.clone()
+    };
     let log = config.print_flags.contains(PrintFlags::LOGS);
     let overwrite = config.print_flags.contains(PrintFlags::OVERWRITE);
+    let verify_idempotent = config.print_flags.contains(PrintFlags::VERIFY_IDEMPOTENT);
+    let message_format = config.message_format;
+    let mut unstable_files = 0;
+
+    // the cache only knows what we left files looking like, so it's no use unless we're writing
+    // them, and it'd skip files that anything printing or checking them needs to see.
+    let mut cache = config
+        .cache
+        .clone()
+        .filter(|_| overwrite && log == false && verify_idempotent == false && config.emit.is_none())
+        .filter(|_| config.print_flags.contains(PrintFlags::LOG_AST) == false)
+        .map(|path| Cache::open(path, lang_config));
 
     for this_file in &config.files {
-        let contents = fs::read_to_string(this_file)?;
+        let source = match fs::read(this_file)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| encoding::decode(bytes, lang_config.encoding.as_deref()))
+        {
+            Ok(source) => source,
+            Err(e) => {
+                Diagnostic::new(Severity::Error, this_file, format!("could not read file: {}", e)).emit(message_format);
+                continue;
+            }
+        };
+        let contents = source.text;
+
+        if let Some(cache) = &mut cache {
+            if cache.is_fresh(this_file, &contents) {
+                continue;
+            }
+        }
+
+        if yy::is_yy(this_file) {
+            if config.emit.is_some() {
+                Diagnostic::new(Severity::Warning, this_file, "--emit skips .yy files".to_owned()).emit(message_format);
+                continue;
+            }
+
+            match yy::format_yy(&contents, lang_config) {
+                Ok(output) => {
+                    if log {
+                        println!("=========OUTPUT=========");
+                        println!("{}", output);
+                    }
+                    if overwrite && output != contents {
+                        write_output(this_file, &output, source.encoding, message_format)?;
+                    }
+                    if let Some(cache) = &mut cache {
+                        cache.record(this_file, &output);
+                    }
+                }
+                Err(e) => yy::describe_error(this_file, &contents, &e).emit(message_format),
+            }
+            continue;
+        }
+
+        if gmx::is_gmx(this_file) {
+            if config.emit.is_some() {
+                Diagnostic::new(Severity::Warning, this_file, "--emit skips .gmx files".to_owned())
+                    .emit(message_format);
+                continue;
+            }
+
+            let (output, diagnostics) = gmx::format_xml(this_file, &contents, lang_config);
+            if let (Some(cache), true) = (&mut cache, diagnostics.is_empty()) {
+                cache.record(this_file, &output);
+            }
+            for diagnostic in diagnostics {
+                diagnostic.emit(message_format);
+            }
+            if log {
+                println!("=========OUTPUT=========");
+                println!("{}", output);
+            }
+            if overwrite && output != contents {
+                write_output(this_file, &output, source.encoding, message_format)?;
+            }
+            continue;
+        }
+
+        if let Some(emit) = config.emit {
+            match emit::to_json(this_file, &contents, emit, lang_config) {
+                Ok(json) => println!("{}", json),
+                Err(e) => Diagnostic::from_format_error(this_file, line_endings::strip_bom(&contents), &e)
+                    .emit(message_format),
+            }
+            continue;
+        }
 
         if contents.contains("// @gml_fmt ignore") {
             continue;
@@ -33,6 +162,21 @@ pub fn run_with_config(config: &Config, lang_config: &LangConfig) -> AnyResult<(
             println!("{}", contents);
         }
 
+        let layout = SourceLayout::detect(&contents);
+        if layout.is_mixed() {
+            let message = format!(
+                "mixed line endings ({} CRLF, {} LF). They will all be made {}.",
+                layout.crlf_count,
+                layout.lf_count,
+                if layout.resolve(lang_config.line_endings) == "\r\n" {
+                    "CRLF"
+                } else {
+                    "LF"
+                }
+            );
+            Diagnostic::new(Severity::Warning, this_file, message).emit(message_format);
+        }
+
         let mut ast_log = if config.print_flags.contains(PrintFlags::LOG_AST) {
             Some(String::new())
         } else {
@@ -51,44 +195,113 @@ pub fn run_with_config(config: &Config, lang_config: &LangConfig) -> AnyResult<(
                     println!("{}", ast);
                 }
 
+                if verify_idempotent {
+                    match run(&output, lang_config, None) {
+                        Ok(second_pass) if second_pass == output => {}
+                        Ok(second_pass) => {
+                            unstable_files += 1;
+                            Diagnostic::new(
+                                Severity::Error,
+                                this_file,
+                                "formatting is not idempotent: the output changes when formatted again".to_owned(),
+                            )
+                            .with_note(diff::unified_diff(&output, &second_pass, "first pass", "second pass"))
+                            .emit(message_format);
+                        }
+                        Err(e) => {
+                            unstable_files += 1;
+                            let mut diagnostic = Diagnostic::from_format_error(this_file, &output, &e);
+                            diagnostic.message =
+                                format!("could not parse the formatted output: {}", diagnostic.message);
+                            diagnostic.emit(message_format);
+                        }
+                    }
+                }
+
                 if overwrite {
-                    fs::write(this_file, output)?;
+                    if let Err(divergence) = check_equivalence_in(&contents, &output, lang_config.dialect) {
+                        let mut diagnostic = Diagnostic::new(
+                            Severity::Error,
+                            this_file,
+                            format!("refusing to write file: {}", divergence),
+                        )
+                        .with_hint("this is a bug in gml_fmt, so please report it along with this file");
+                        if divergence.original_line != 0 {
+                            diagnostic = diagnostic.at(Position::new(
+                                &contents,
+                                divergence.original_line - 1,
+                                divergence.original_column - 1,
+                                1,
+                            ));
+                        }
+                        diagnostic.emit(message_format);
+                        continue;
+                    }
+
+                    write_output(this_file, &output, source.encoding, message_format)?;
+                    if let Some(cache) = &mut cache {
+                        cache.record(this_file, &output);
+                    }
                 }
             }
             Err(e) => {
-                println!("Could not parse file {:?}", this_file);
-                println!("{}", e);
+                Diagnostic::from_format_error(this_file, line_endings::strip_bom(&contents), &e).emit(message_format);
             }
         }
     }
 
-    Ok(())
-}
-
-pub fn run(source: &str, lang_config: &LangConfig, print_ast: Option<&mut String>) -> AnyResult<String> {
-    let source_size = source.len();
-    match Parser::new(source).build_ast() {
-        Ok(ast) => {
-            if let Some(give_ast) = print_ast {
-                *give_ast = format!("{:#?}", ast);
-            }
+    if let Some(cache) = cache {
+        if let Err(e) = cache.save() {
+            let path = config.cache.as_deref().unwrap_or_else(|| Path::new(CACHE_FILE));
+            Diagnostic::new(Severity::Warning, path, format!("could not write the cache: {}", e)).emit(message_format);
+        }
+    }
 
-            let printer = Printer::new(source_size / 2, lang_config).autoformat(&ast);
+    if unstable_files != 0 {
+        anyhow::bail!("{} file(s) changed again when formatted a second time", unstable_files);
+    }
 
-            Ok(printer.get_output(source_size))
-        }
+    Ok(())
+}
 
+/// Writes `output` back over `this_file`, in the encoding it was read in.
+fn write_output(
+    this_file: &Path,
+    output: &str,
+    encoding: &'static encoding_rs::Encoding,
+    message_format: MessageFormat,
+) -> FormatResult<()> {
+    match encoding::encode(output, encoding) {
+        Ok(bytes) => fs::write(this_file, bytes).map_err(|source| FormatError::Io {
+            path: this_file.to_path_buf(),
+            source,
+        }),
         Err(e) => {
-            anyhow::bail!("{}", e);
+            Diagnostic::new(Severity::Error, this_file, format!("could not write file: {}", e)).emit(message_format);
+            Ok(())
         }
     }
 }
 
-pub fn run_snippet(source: &str, lang_config: Option<LangConfig>) -> AnyResult<String> {
-    let source_size = source.len();
-    let ast = Parser::new(source).build_ast()?;
+/// Reads `path` in the encoding `lang_config` asks for, like a run does, for tools which format
+/// a file's text themselves. Any BOM is kept.
+pub fn read_source(path: &Path, lang_config: &LangConfig) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    Ok(encoding::decode(bytes, lang_config.encoding.as_deref())?.text)
+}
+
+pub fn run(source: &str, lang_config: &LangConfig, print_ast: Option<&mut String>) -> FormatResult<String> {
+    let ast = ast::parse_with(source, lang_config)?;
+    if let Some(give_ast) = print_ast {
+        *give_ast = format!("{:#?}", ast.statements);
+    }
+
+    Ok(format_ast(&ast, lang_config))
+}
+
+pub fn run_snippet(source: &str, lang_config: Option<LangConfig>) -> FormatResult<String> {
     let config = lang_config.unwrap_or_default();
-    let printer = Printer::new(source_size / 2, &config).autoformat(&ast);
+    let ast = ast::parse_with(source, &config)?;
 
-    Ok(printer.get_output(source_size))
+    Ok(format_ast(&ast, &config))
 }
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+const BOM: &str = "\u{feff}";
+
+/// Which line ending we write out. `Auto` keeps whichever ending a file mostly used already.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    #[default]
+    Auto,
+    Lf,
+    Crlf,
+    Native,
+}
+
+/// What a source file looked like before we formatted it, so that we can put it back the same way.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SourceLayout {
+    pub has_bom: bool,
+    pub crlf_count: usize,
+    pub lf_count: usize,
+}
+
+impl SourceLayout {
+    pub fn detect(source: &str) -> SourceLayout {
+        let crlf_count = source.matches("\r\n").count();
+
+        SourceLayout {
+            has_bom: source.starts_with(BOM),
+            crlf_count,
+            lf_count: source.matches('\n').count() - crlf_count,
+        }
+    }
+
+    pub fn is_mixed(&self) -> bool {
+        self.crlf_count != 0 && self.lf_count != 0
+    }
+
+    /// The ending we'll print with, given what the user asked for.
+    pub fn resolve(&self, line_endings: LineEndings) -> &'static str {
+        match line_endings {
+            LineEndings::Auto => {
+                if self.crlf_count > self.lf_count {
+                    "\r\n"
+                } else {
+                    "\n"
+                }
+            }
+            LineEndings::Lf => "\n",
+            LineEndings::Crlf => "\r\n",
+            LineEndings::Native => {
+                if cfg!(windows) {
+                    "\r\n"
+                } else {
+                    "\n"
+                }
+            }
+        }
+    }
+
+    /// Takes the `\n` only output of the Printer, and gives it the ending and BOM the file should have.
+    /// Multiline strings and comments are copied out of the source as is, so we normalize them first.
+    pub fn restore(&self, output: String, line_endings: LineEndings) -> String {
+        let ending = self.resolve(line_endings);
+        let has_crlf = output.contains("\r\n");
+
+        let output = if ending == "\r\n" {
+            let normalized = if has_crlf { output.replace("\r\n", "\n") } else { output };
+            normalized.replace('\n', "\r\n")
+        } else if has_crlf {
+            output.replace("\r\n", "\n")
+        } else {
+            output
+        };
+
+        if self.has_bom {
+            format!("{}{}", BOM, output)
+        } else {
+            output
+        }
+    }
+}
+
+/// Removes a UTF-8 BOM, which the Scanner would otherwise see as unidentified input.
+pub fn strip_bom(source: &str) -> &str {
+    source.strip_prefix(BOM).unwrap_or(source)
+}
@@ -1,15 +1,18 @@
+use super::error::{FormatError, FormatResult};
 use super::expressions::*;
 use super::lex_token::TokenType;
 use super::lex_token::*;
-use super::scanner::Scanner;
+use super::scanner::{Dialect, Scanner};
 use super::statements::*;
-use anyhow::Result as AnyResult;
 use std::iter::Peekable;
 
 pub struct Parser<'a> {
     pub ast: Vec<StmtBox<'a>>,
     allow_unidentified: bool,
+    source: &'a str,
     scanner: Peekable<Scanner<'a>>,
+    recover_from_errors: bool,
+    previous_end: usize,
     can_pair: bool,
     leftover_stmts: Vec<StmtBox<'a>>,
     check_leftovers: bool,
@@ -19,7 +22,10 @@ impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Parser<'a> {
         Parser {
             ast: Vec::new(),
+            source: input,
             scanner: Scanner::new(input).into_iter().peekable(),
+            recover_from_errors: false,
+            previous_end: 0,
             allow_unidentified: false,
             can_pair: true,
             leftover_stmts: Vec::new(),
@@ -27,10 +33,22 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn build_ast(mut self) -> AnyResult<Vec<StmtBox<'a>>> {
+    /// Instead of failing on a statement we can't parse, keep it as written in a `Statement::Verbatim`
+    /// and carry on from the next statement boundary.
+    pub fn recover_from_errors(mut self) -> Parser<'a> {
+        self.recover_from_errors = true;
+        self
+    }
+
+    pub fn with_dialect(mut self, dialect: Dialect) -> Parser<'a> {
+        self.scanner = Scanner::new(self.source).with_dialect(dialect).peekable();
+        self
+    }
+
+    pub fn build_ast(mut self) -> FormatResult<Vec<StmtBox<'a>>> {
         while let Some(_) = self.scanner.peek() {
             self.can_pair = true;
-            let ret = self.statement()?;
+            let ret = self.statement_or_verbatim()?;
             self.ast.push(ret);
 
             if self.check_leftovers {
@@ -42,7 +60,20 @@ impl<'a> Parser<'a> {
         Ok(self.ast)
     }
 
-    fn statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn statement(&mut self) -> FormatResult<StmtBox<'a>> {
+        self.spanned(Parser::statement_kind)
+    }
+
+    /// Parses a statement with `parse`, and gives it the span of every token it took.
+    fn spanned(&mut self, parse: fn(&mut Self) -> FormatResult<StmtBox<'a>>) -> FormatResult<StmtBox<'a>> {
+        let start = self.next_token_start();
+        let mut statement = parse(self)?;
+        statement.span = Span::new(start, self.previous_end);
+
+        Ok(statement)
+    }
+
+    fn statement_kind(&mut self) -> FormatResult<StmtBox<'a>> {
         if let Some(token) = self.scanner.peek() {
             match token.token_type {
                 TokenType::Comment(_) => {
@@ -72,6 +103,12 @@ impl<'a> Parser<'a> {
                     self.consume_next();
                     return self.define_statement();
                 }
+                TokenType::Header(_) => {
+                    let start = token.span.start;
+                    let blank_line_before = self.blank_line_before(start);
+                    let header = self.consume_next();
+                    return self.header_statement(header, blank_line_before);
+                }
                 TokenType::Var | TokenType::GlobalVar => {
                     return self.series_var_declaration();
                 }
@@ -121,19 +158,96 @@ impl<'a> Parser<'a> {
         self.expression_statement()
     }
 
-    fn define_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn statement_or_verbatim(&mut self) -> FormatResult<StmtBox<'a>> {
+        if self.recover_from_errors == false {
+            return self.statement();
+        }
+
+        let checkpoint = self.scanner.clone();
+        let previous_end = self.previous_end;
+        let leftover_count = self.leftover_stmts.len();
+        let check_leftovers = self.check_leftovers;
+
+        match self.statement() {
+            Ok(statement) => Ok(statement),
+            Err(_) => {
+                self.scanner = checkpoint;
+                self.previous_end = previous_end;
+                self.leftover_stmts.truncate(leftover_count);
+                self.check_leftovers = check_leftovers;
+                self.can_pair = true;
+
+                Ok(self.verbatim_statement())
+            }
+        }
+    }
+
+    /// Skips to the end of the statement we're on, which is a `;` or a newline outside of any brackets,
+    /// or the `}` which closes the block we're in, and hands back everything we skipped as written.
+    fn verbatim_statement(&mut self) -> StmtBox<'a> {
+        let start = self.next_token_start();
+        let mut depth = 0;
+        let mut first = true;
+
+        while let Some(token) = self.scanner.peek() {
+            match token.token_type {
+                TokenType::LeftParen
+                | TokenType::LeftBrace
+                | TokenType::LeftBracket
+                | TokenType::ArrayIndexer
+                | TokenType::MapIndexer
+                | TokenType::ListIndexer
+                | TokenType::GridIndexer => depth += 1,
+
+                TokenType::RightParen | TokenType::RightBracket => depth = i32::max(depth - 1, 0),
+
+                TokenType::RightBrace => {
+                    if depth == 0 && first == false {
+                        break;
+                    }
+                    depth = i32::max(depth - 1, 0);
+                }
+
+                TokenType::Semicolon if depth == 0 => {
+                    self.consume_next();
+                    break;
+                }
+
+                TokenType::Newline(_) if depth == 0 && first == false => break,
+
+                _ => {}
+            }
+
+            self.consume_next();
+            first = false;
+        }
+
+        let mut statement = StatementWrapper::new(Statement::Verbatim(&self.source[start..self.previous_end]), false);
+        statement.span = Span::new(start, self.previous_end);
+        statement
+    }
+
+    /// Where the next token starts in our source, or the end of the source if we're out of tokens.
+    fn next_token_start(&mut self) -> usize {
+        match self.scanner.peek() {
+            Some(token) => token.span.start,
+            None => self.source.len(),
+        }
+    }
+
+    fn define_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let comments_after_control_word = self.get_newlines_and_comments();
         let script_name = self.expression()?;
         let mut body = vec![];
 
         while let Some(token) = self.scanner.peek() {
             match token.token_type {
-                TokenType::Define => {
+                TokenType::Define | TokenType::Header(_) => {
                     break;
                 }
 
                 _ => {
-                    body.push(self.statement()?);
+                    body.push(self.statement_or_verbatim()?);
                 }
             }
         }
@@ -148,8 +262,43 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn series_var_declaration(&mut self) -> AnyResult<StmtBox<'a>> {
-        let starting_var_type = self.scanner.next().unwrap();
+    fn header_statement(&mut self, header: Token<'a>, blank_line_before: bool) -> FormatResult<StmtBox<'a>> {
+        let mut body = vec![];
+
+        while let Some(token) = self.scanner.peek() {
+            match token.token_type {
+                // a header inside a block ends with it, and leaves its `}` for the block.
+                TokenType::Define | TokenType::Header(_) | TokenType::RightBrace => {
+                    break;
+                }
+
+                _ => {
+                    body.push(self.statement_or_verbatim()?);
+                }
+            }
+        }
+
+        Ok(StatementWrapper::new(
+            Statement::Header {
+                header,
+                blank_line_before,
+                body,
+            },
+            false,
+        ))
+    }
+
+    /// Whether the line right above `start` is empty. The newlines and comments we skipped since
+    /// the last token we took are still in the source between the two.
+    fn blank_line_before(&self, start: usize) -> bool {
+        let gap: Vec<&str> = self.source[usize::min(self.previous_end, start)..start]
+            .split('\n')
+            .collect();
+        gap.len() > 2 && gap[gap.len() - 2].trim().is_empty()
+    }
+
+    fn series_var_declaration(&mut self) -> FormatResult<StmtBox<'a>> {
+        let starting_var_type = self.consume_next();
         let comments_after_control_word = self.get_newlines_and_comments();
         let var_decl = self.var_declaration()?;
         let has_semicolon = self.check_next_consume(TokenType::Semicolon);
@@ -164,7 +313,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn var_declaration(&mut self) -> AnyResult<DelimitedLines<'a, VariableDecl<'a>>> {
+    fn var_declaration(&mut self) -> FormatResult<DelimitedLines<'a, VariableDecl<'a>>> {
         let mut arguments: Vec<DelimitedLine<'a, VariableDecl<'a>>> = Vec::new();
 
         let end_delimiter;
@@ -180,7 +329,7 @@ impl<'a> Parser<'a> {
             let mut say_var_comments = None;
 
             if has_var {
-                say_var = Some(self.scanner.next().unwrap());
+                say_var = Some(self.consume_next());
                 say_var_comments = Some(self.get_newlines_and_comments());
             }
 
@@ -213,10 +362,11 @@ impl<'a> Parser<'a> {
                     // Ah shit you suck.
                     let has_semicolon = self.check_next_consume(TokenType::Semicolon);
                     self.check_leftovers = true;
-                    self.leftover_stmts.push(StatementWrapper::new(
-                        Statement::ExpresssionStatement { expression: var_expr },
-                        has_semicolon,
-                    ));
+                    let span = Span::new(var_expr.span.start, self.previous_end);
+                    let mut leftover =
+                        StatementWrapper::new(Statement::ExpresssionStatement { expression: var_expr }, has_semicolon);
+                    leftover.span = span;
+                    self.leftover_stmts.push(leftover);
                     end_delimiter = true; // we never woulda gotten here if not for you cursed end delimiters!
                     break;
                 }
@@ -247,7 +397,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn block(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn block(&mut self) -> FormatResult<StmtBox<'a>> {
         let comments_after_lbrace = self.get_newlines_and_comments();
 
         let mut statements = Vec::new();
@@ -256,7 +406,7 @@ impl<'a> Parser<'a> {
             if self.check_next_consume(TokenType::RightBrace) {
                 break;
             } else {
-                statements.push(self.statement()?);
+                statements.push(self.statement_or_verbatim()?);
             }
         }
 
@@ -271,7 +421,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn if_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn if_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let comments_after_control_word = self.get_newlines_and_comments();
         let condition = self.expression()?;
         let then_branch = self.statement()?;
@@ -295,7 +445,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn while_with_repeat(&mut self, token: Token<'a>) -> AnyResult<StmtBox<'a>> {
+    fn while_with_repeat(&mut self, token: Token<'a>) -> FormatResult<StmtBox<'a>> {
         let comments_after_control_word = self.get_newlines_and_comments();
         let condition = self.expression()?;
         let body = self.statement()?;
@@ -312,7 +462,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn do_until_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn do_until_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let comments_after_control_word = self.get_newlines_and_comments();
         let body = self.statement()?;
         let comments_between = self.get_newlines_and_comments();
@@ -331,7 +481,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn switch_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn switch_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let comments_after_control_word = self.get_newlines_and_comments();
         let condition = self.expression()?;
         self.check_next_consume(TokenType::LeftBrace);
@@ -358,7 +508,7 @@ impl<'a> Parser<'a> {
                                 break;
                             }
                             _ => {
-                                statements.push(self.statement()?);
+                                statements.push(self.statement_or_verbatim()?);
                             }
                         }
                     }
@@ -384,7 +534,7 @@ impl<'a> Parser<'a> {
                                 break;
                             }
                             _ => {
-                                statements.push(self.statement()?);
+                                statements.push(self.statement_or_verbatim()?);
                             }
                         }
                     }
@@ -400,7 +550,7 @@ impl<'a> Parser<'a> {
                 TokenType::RightBrace => break,
 
                 _ => {
-                    anyhow::bail!("Unknown token {} in Switch statement", token);
+                    return Err(FormatError::unexpected_token(token, vec!["`case`", "`default`", "`}`"]));
                 }
             }
         }
@@ -420,7 +570,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn for_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn for_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let comments_after_control_word = self.get_newlines_and_comments();
 
         self.check_next_consume(TokenType::LeftParen);
@@ -429,9 +579,9 @@ impl<'a> Parser<'a> {
         let initializer = if self.check_next_consume(TokenType::Semicolon) {
             None
         } else if self.check_next(TokenType::Var) {
-            Some(self.series_var_declaration()?)
+            Some(self.spanned(Parser::series_var_declaration)?)
         } else {
-            Some(self.expression_statement()?)
+            Some(self.spanned(Parser::expression_statement)?)
         };
         let comments_after_initializer = self.get_newlines_and_comments();
 
@@ -473,7 +623,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn return_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn return_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let expression = if self.check_next(TokenType::Semicolon) {
             None
         } else {
@@ -484,17 +634,17 @@ impl<'a> Parser<'a> {
         Ok(StatementWrapper::new(Statement::Return { expression }, has_semicolon))
     }
 
-    fn break_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn break_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let has_semicolon = self.check_next_consume(TokenType::Semicolon);
         Ok(StatementWrapper::new(Statement::Break, has_semicolon))
     }
 
-    fn exit_statment(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn exit_statment(&mut self) -> FormatResult<StmtBox<'a>> {
         let has_semicolon = self.check_next_consume(TokenType::Semicolon);
         Ok(StatementWrapper::new(Statement::Exit, has_semicolon))
     }
 
-    fn enum_declaration(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn enum_declaration(&mut self) -> FormatResult<StmtBox<'a>> {
         let comments_after_control_word = self.get_newlines_and_comments();
         let name = self.expression()?;
 
@@ -515,7 +665,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn expression_statement(&mut self) -> AnyResult<StmtBox<'a>> {
+    fn expression_statement(&mut self) -> FormatResult<StmtBox<'a>> {
         let expr = self.expression()?;
         let has_semicolon = self.check_next_consume(TokenType::Semicolon);
 
@@ -525,7 +675,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn expression(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn expression(&mut self) -> FormatResult<ExprBox<'a>> {
         self.allow_unidentified = true;
         let ret = self.assignment()?;
         self.can_pair = true;
@@ -534,7 +684,7 @@ impl<'a> Parser<'a> {
         Ok(ret)
     }
 
-    fn assignment(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn assignment(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.ternary()?;
 
         if self.can_pair {
@@ -549,16 +699,19 @@ impl<'a> Parser<'a> {
                     | TokenType::BitOrEquals
                     | TokenType::BitAndEquals
                     | TokenType::ModEquals => {
-                        let operator = self.scanner.next().unwrap();
+                        let operator = self.consume_next();
                         let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
                         let assignment_expr = self.assignment()?;
 
-                        expr = self.create_expr_box_no_comment(Expr::Assign {
-                            left: expr,
-                            operator: operator,
-                            comments_and_newlines_between_op_and_r,
-                            right: assignment_expr,
-                        });
+                        expr = self.create_expr_box_no_comment(
+                            expr.span.start,
+                            Expr::Assign {
+                                left: expr,
+                                operator: operator,
+                                comments_and_newlines_between_op_and_r,
+                                right: assignment_expr,
+                            },
+                        );
                     }
 
                     _ => {}
@@ -569,7 +722,7 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn ternary(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn ternary(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.or()?;
 
         if self.check_next_consume(TokenType::Hook) {
@@ -579,92 +732,107 @@ impl<'a> Parser<'a> {
             let comments_and_newlines_after_colon = self.get_newlines_and_comments();
             let right = self.ternary()?;
 
-            expr = self.create_expr_box_no_comment(Expr::Ternary {
-                conditional: expr,
-                comments_and_newlines_after_q,
-                left,
-                comments_and_newlines_after_colon,
-                right,
-            });
+            expr = self.create_expr_box_no_comment(
+                expr.span.start,
+                Expr::Ternary {
+                    conditional: expr,
+                    comments_and_newlines_after_q,
+                    left,
+                    comments_and_newlines_after_colon,
+                    right,
+                },
+            );
         }
 
         Ok(expr)
     }
 
     // parse our Logical Operands here
-    fn or(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn or(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut left = self.and()?;
 
         if self.check_next_either(TokenType::LogicalOr, TokenType::OrAlias) {
-            let token = self.scanner.next().unwrap();
+            let token = self.consume_next();
             let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
             let right = self.or()?;
 
-            left = self.create_expr_box_no_comment(Expr::Binary {
-                left,
-                operator: token,
-                comments_and_newlines_between_op_and_r,
-                right,
-            });
+            left = self.create_expr_box_no_comment(
+                left.span.start,
+                Expr::Binary {
+                    left,
+                    operator: token,
+                    comments_and_newlines_between_op_and_r,
+                    right,
+                },
+            );
         }
 
         Ok(left)
     }
 
-    fn and(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn and(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut left = self.xor()?;
 
         if self.check_next_either(TokenType::LogicalAnd, TokenType::AndAlias) {
-            let token = self.scanner.next().unwrap();
+            let token = self.consume_next();
             let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
             let right = self.and()?;
 
-            left = self.create_expr_box_no_comment(Expr::Binary {
-                left,
-                operator: token,
-                comments_and_newlines_between_op_and_r,
-                right,
-            });
+            left = self.create_expr_box_no_comment(
+                left.span.start,
+                Expr::Binary {
+                    left,
+                    operator: token,
+                    comments_and_newlines_between_op_and_r,
+                    right,
+                },
+            );
         }
 
         Ok(left)
     }
 
-    fn xor(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn xor(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut left = self.equality()?;
 
         if self.check_next_either(TokenType::LogicalXor, TokenType::XorAlias) {
-            let token = self.scanner.next().unwrap();
+            let token = self.consume_next();
             let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
             let right = self.xor()?;
 
-            left = self.create_expr_box_no_comment(Expr::Binary {
-                left,
-                operator: token,
-                comments_and_newlines_between_op_and_r,
-                right,
-            })
+            left = self.create_expr_box_no_comment(
+                left.span.start,
+                Expr::Binary {
+                    left,
+                    operator: token,
+                    comments_and_newlines_between_op_and_r,
+                    right,
+                },
+            )
         }
 
         Ok(left)
     }
 
-    fn equality(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn equality(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.comparison()?;
 
         if self.can_pair {
             while let Some(t) = self.scanner.peek() {
                 if t.token_type == TokenType::EqualEqual || t.token_type == TokenType::BangEqual {
-                    let token = self.scanner.next().unwrap();
+                    let token = self.consume_next();
                     let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
                     let right = self.comparison()?;
 
-                    expr = self.create_expr_box_no_comment(Expr::Binary {
-                        left: expr,
-                        operator: token,
-                        comments_and_newlines_between_op_and_r,
-                        right,
-                    });
+                    expr = self.create_expr_box_no_comment(
+                        expr.span.start,
+                        Expr::Binary {
+                            left: expr,
+                            operator: token,
+                            comments_and_newlines_between_op_and_r,
+                            right,
+                        },
+                    );
                 } else {
                     break;
                 }
@@ -674,23 +842,26 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn comparison(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.binary()?;
 
         if self.can_pair {
             while let Some(t) = self.scanner.peek() {
                 match t.token_type {
                     TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
-                        let t = self.scanner.next().unwrap();
+                        let t = self.consume_next();
                         let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
                         let right = self.binary()?;
 
-                        expr = self.create_expr_box_no_comment(Expr::Binary {
-                            left: expr,
-                            operator: t,
-                            comments_and_newlines_between_op_and_r,
-                            right,
-                        });
+                        expr = self.create_expr_box_no_comment(
+                            expr.span.start,
+                            Expr::Binary {
+                                left: expr,
+                                operator: t,
+                                comments_and_newlines_between_op_and_r,
+                                right,
+                            },
+                        );
                     }
                     _ => break,
                 };
@@ -700,23 +871,26 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn binary(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn binary(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.bitshift()?;
 
         if self.can_pair {
             while let Some(t) = self.scanner.peek() {
                 match t.token_type {
                     TokenType::BitAnd | TokenType::BitOr | TokenType::BitXor => {
-                        let t = self.scanner.next().unwrap();
+                        let t = self.consume_next();
                         let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
                         let right = self.bitshift()?;
 
-                        expr = self.create_expr_box_no_comment(Expr::Binary {
-                            left: expr,
-                            operator: t,
-                            comments_and_newlines_between_op_and_r,
-                            right,
-                        });
+                        expr = self.create_expr_box_no_comment(
+                            expr.span.start,
+                            Expr::Binary {
+                                left: expr,
+                                operator: t,
+                                comments_and_newlines_between_op_and_r,
+                                right,
+                            },
+                        );
                     }
                     _ => break,
                 }
@@ -726,23 +900,26 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn bitshift(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn bitshift(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.addition()?;
 
         if self.can_pair {
             while let Some(t) = self.scanner.peek() {
                 match t.token_type {
                     TokenType::BitLeft | TokenType::BitRight => {
-                        let t = self.scanner.next().unwrap();
+                        let t = self.consume_next();
                         let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
                         let right = self.addition()?;
 
-                        expr = self.create_expr_box_no_comment(Expr::Binary {
-                            left: expr,
-                            operator: t,
-                            comments_and_newlines_between_op_and_r,
-                            right,
-                        });
+                        expr = self.create_expr_box_no_comment(
+                            expr.span.start,
+                            Expr::Binary {
+                                left: expr,
+                                operator: t,
+                                comments_and_newlines_between_op_and_r,
+                                right,
+                            },
+                        );
                     }
                     _ => break,
                 }
@@ -752,23 +929,26 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn addition(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn addition(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.multiplication()?;
 
         if self.can_pair {
             while let Some(t) = self.scanner.peek() {
                 match t.token_type {
                     TokenType::Minus | TokenType::Plus => {
-                        let token = self.scanner.next().unwrap();
+                        let token = self.consume_next();
                         let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
                         let right = self.multiplication()?;
 
-                        expr = self.create_expr_box_no_comment(Expr::Binary {
-                            left: expr,
-                            operator: token,
-                            comments_and_newlines_between_op_and_r,
-                            right,
-                        });
+                        expr = self.create_expr_box_no_comment(
+                            expr.span.start,
+                            Expr::Binary {
+                                left: expr,
+                                operator: token,
+                                comments_and_newlines_between_op_and_r,
+                                right,
+                            },
+                        );
                     }
                     _ => break,
                 };
@@ -778,23 +958,26 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn multiplication(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn multiplication(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.unary()?;
 
         if self.can_pair {
             while let Some(t) = self.scanner.peek() {
                 match t.token_type {
                     TokenType::Slash | TokenType::Star | TokenType::Mod | TokenType::ModAlias | TokenType::Div => {
-                        let token = self.scanner.next().unwrap();
+                        let token = self.consume_next();
                         let comments_and_newlines_between_op_and_r = self.get_newlines_and_comments();
                         let right = self.unary()?;
 
-                        expr = self.create_expr_box_no_comment(Expr::Binary {
-                            left: expr,
-                            operator: token,
-                            comments_and_newlines_between_op_and_r,
-                            right,
-                        });
+                        expr = self.create_expr_box_no_comment(
+                            expr.span.start,
+                            Expr::Binary {
+                                left: expr,
+                                operator: token,
+                                comments_and_newlines_between_op_and_r,
+                                right,
+                            },
+                        );
                     }
                     _ => break,
                 };
@@ -804,32 +987,38 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn unary(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn unary(&mut self) -> FormatResult<ExprBox<'a>> {
         if self.can_pair {
             if let Some(t) = self.scanner.peek() {
                 match t.token_type {
                     TokenType::Bang | TokenType::Minus | TokenType::Plus | TokenType::Tilde | TokenType::NotAlias => {
-                        let t = self.scanner.next().unwrap();
+                        let t = self.consume_next();
                         let comments_and_newlines_between = self.get_newlines_and_comments();
                         let right = self.unary()?;
 
-                        return Ok(self.create_expr_box_no_comment(Expr::Unary {
-                            operator: t,
-                            comments_and_newlines_between,
-                            right,
-                        }));
+                        return Ok(self.create_expr_box_no_comment(
+                            t.span.start,
+                            Expr::Unary {
+                                operator: t,
+                                comments_and_newlines_between,
+                                right,
+                            },
+                        ));
                     }
 
                     TokenType::Incrementer | TokenType::Decrementer => {
-                        let t = self.scanner.next().unwrap();
+                        let t = self.consume_next();
                         let comments_and_newlines_between = self.get_newlines_and_comments();
                         let right = self.unary()?;
 
-                        return Ok(self.create_expr_box_no_comment(Expr::Unary {
-                            operator: t,
-                            comments_and_newlines_between,
-                            right,
-                        }));
+                        return Ok(self.create_expr_box_no_comment(
+                            t.span.start,
+                            Expr::Unary {
+                                operator: t,
+                                comments_and_newlines_between,
+                                right,
+                            },
+                        ));
                     }
 
                     _ => {}
@@ -840,35 +1029,41 @@ impl<'a> Parser<'a> {
         self.postfix()
     }
 
-    fn postfix(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn postfix(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expr = self.call()?;
 
         if self.check_next_either(TokenType::Incrementer, TokenType::Decrementer) {
-            let t = self.scanner.next().unwrap();
+            let t = self.consume_next();
 
             let comments_and_newlines_between = self.get_newlines_and_comments();
-            expr = self.create_expr_box_no_comment(Expr::Postfix {
-                operator: t,
-                comments_and_newlines_between,
-                expr,
-            });
+            expr = self.create_expr_box_no_comment(
+                expr.span.start,
+                Expr::Postfix {
+                    operator: t,
+                    comments_and_newlines_between,
+                    expr,
+                },
+            );
         }
 
         Ok(expr)
     }
 
-    fn call(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn call(&mut self) -> FormatResult<ExprBox<'a>> {
         let mut expression = self.primary()?;
 
         if self.check_next_consume(TokenType::LeftParen) {
             let comments_and_newlines_after_lparen = self.get_newlines_and_comments();
             let arguments = self.finish_call(TokenType::RightParen, TokenType::Comma)?;
 
-            expression = self.create_comment_expr_box(Expr::Call {
-                procedure_name: expression,
-                arguments,
-                comments_and_newlines_after_lparen,
-            });
+            expression = self.create_comment_expr_box(
+                expression.span.start,
+                Expr::Call {
+                    procedure_name: expression,
+                    arguments,
+                    comments_and_newlines_after_lparen,
+                },
+            );
         }
 
         while let Some(token) = self.scanner.peek() {
@@ -877,11 +1072,14 @@ impl<'a> Parser<'a> {
                     self.consume_next();
                     let comments_between = self.get_newlines_and_comments();
                     let instance_variable = self.call()?;
-                    expression = self.create_comment_expr_box(Expr::DotAccess {
-                        object_name: expression,
-                        comments_between,
-                        instance_variable,
-                    });
+                    expression = self.create_comment_expr_box(
+                        expression.span.start,
+                        Expr::DotAccess {
+                            object_name: expression,
+                            comments_between,
+                            instance_variable,
+                        },
+                    );
                 }
 
                 TokenType::LeftBracket
@@ -889,7 +1087,7 @@ impl<'a> Parser<'a> {
                 | TokenType::MapIndexer
                 | TokenType::ListIndexer
                 | TokenType::GridIndexer => {
-                    let access_type = self.scanner.next().unwrap();
+                    let access_type = self.consume_next();
                     let mut access_exprs = vec![];
 
                     while let Some(token) = self.scanner.peek() {
@@ -905,11 +1103,14 @@ impl<'a> Parser<'a> {
                     }
 
                     self.check_next_consume(TokenType::RightBracket);
-                    expression = self.create_comment_expr_box(Expr::DataStructureAccess {
-                        ds_name: expression,
-                        access_type,
-                        access_exprs,
-                    });
+                    expression = self.create_comment_expr_box(
+                        expression.span.start,
+                        Expr::DataStructureAccess {
+                            ds_name: expression,
+                            access_type,
+                            access_exprs,
+                        },
+                    );
                 }
 
                 _ => break,
@@ -919,40 +1120,49 @@ impl<'a> Parser<'a> {
         Ok(expression)
     }
 
-    fn primary(&mut self) -> AnyResult<ExprBox<'a>> {
+    fn primary(&mut self) -> FormatResult<ExprBox<'a>> {
         if let Some(t) = self.scanner.peek() {
             let output = match t.token_type {
                 TokenType::Number(_) | TokenType::String(_) => {
                     let t = self.consume_next();
                     let comments = self.get_newlines_and_comments();
-                    self.create_expr_box_no_comment(Expr::Literal {
-                        literal_token: t,
-                        comments,
-                    })
+                    self.create_expr_box_no_comment(
+                        t.span.start,
+                        Expr::Literal {
+                            literal_token: t,
+                            comments,
+                        },
+                    )
                 }
                 TokenType::NumberStartDot(_) => {
                     let t = self.consume_next();
                     let comments = self.get_newlines_and_comments();
-                    self.create_expr_box_no_comment(Expr::NumberStartDot {
-                        literal_token: t,
-                        comments,
-                    })
+                    self.create_expr_box_no_comment(
+                        t.span.start,
+                        Expr::NumberStartDot {
+                            literal_token: t,
+                            comments,
+                        },
+                    )
                 }
                 TokenType::NumberEndDot(_) => {
                     let t = self.consume_next();
                     let comments = self.get_newlines_and_comments();
-                    self.create_expr_box_no_comment(Expr::NumberEndDot {
-                        literal_token: t,
-                        comments,
-                    })
+                    self.create_expr_box_no_comment(
+                        t.span.start,
+                        Expr::NumberEndDot {
+                            literal_token: t,
+                            comments,
+                        },
+                    )
                 }
                 TokenType::Identifier(_) => {
                     let t = self.consume_next();
                     let comments = self.get_newlines_and_comments();
-                    self.create_expr_box_no_comment(Expr::Identifier { name: t, comments })
+                    self.create_expr_box_no_comment(t.span.start, Expr::Identifier { name: t, comments })
                 }
                 TokenType::LeftParen => {
-                    self.consume_next();
+                    let lparen = self.consume_next();
                     let comments_and_newlines_after_lparen = self.get_newlines_and_comments();
 
                     let mut expressions = vec![];
@@ -963,60 +1173,72 @@ impl<'a> Parser<'a> {
 
                     let comments_and_newlines_after_rparen = self.get_newlines_and_comments();
 
-                    self.create_expr_box_no_comment(Expr::Grouping {
-                        expressions,
-                        comments_and_newlines_after_lparen,
-                        comments_and_newlines_after_rparen,
-                    })
+                    self.create_expr_box_no_comment(
+                        lparen.span.start,
+                        Expr::Grouping {
+                            expressions,
+                            comments_and_newlines_after_lparen,
+                            comments_and_newlines_after_rparen,
+                        },
+                    )
                 }
 
                 TokenType::LeftBracket => {
-                    self.consume_next();
+                    let lbracket = self.consume_next();
                     let comments_and_newlines_after_lbracket = self.get_newlines_and_comments();
                     let arguments = self.finish_call(TokenType::RightBracket, TokenType::Comma)?;
 
-                    self.create_expr_box_no_comment(Expr::ArrayLiteral {
-                        comments_and_newlines_after_lbracket,
-                        arguments,
-                    })
+                    self.create_expr_box_no_comment(
+                        lbracket.span.start,
+                        Expr::ArrayLiteral {
+                            comments_and_newlines_after_lbracket,
+                            arguments,
+                        },
+                    )
                 }
 
                 TokenType::Newline(_) => {
-                    self.consume_next();
+                    let newline = self.consume_next();
                     self.can_pair = false;
-                    self.create_expr_box_no_comment(Expr::Newline)
+                    self.create_expr_box_no_comment(newline.span.start, Expr::Newline)
                 }
                 TokenType::Comment(_) => {
                     let comment = self.consume_next();
                     self.can_pair = false;
-                    self.create_expr_box_no_comment(Expr::Comment { comment })
+                    self.create_expr_box_no_comment(comment.span.start, Expr::Comment { comment })
                 }
                 TokenType::MultilineComment(_) => {
                     let multiline_comment = self.consume_next();
                     self.can_pair = false;
-                    self.create_expr_box_no_comment(Expr::MultilineComment { multiline_comment })
+                    self.create_expr_box_no_comment(
+                        multiline_comment.span.start,
+                        Expr::MultilineComment { multiline_comment },
+                    )
                 }
                 _ => {
                     let literal_token = self.consume_next();
                     if self.allow_unidentified == false {
-                        anyhow::bail!("Error parsing {}", literal_token);
+                        return Err(FormatError::unexpected_token(&literal_token, vec!["an expression"]));
                     }
 
-                    self.create_comment_expr_box(Expr::UnidentifiedAsLiteral { literal_token })
+                    self.create_comment_expr_box(
+                        literal_token.span.start,
+                        Expr::UnidentifiedAsLiteral { literal_token },
+                    )
                 }
             };
 
             return Ok(output);
         }
 
-        anyhow::bail!("Unexpected end!");
+        Err(FormatError::unexpected_eof(self.source, vec!["an expression"]))
     }
 
     fn finish_call(
         &mut self,
         end_token_type: TokenType,
         delimiter_type: TokenType,
-    ) -> AnyResult<DelimitedLines<'a, ExprBox<'a>>> {
+    ) -> FormatResult<DelimitedLines<'a, ExprBox<'a>>> {
         let mut arguments = Vec::new();
 
         let mut end_delimiter = true;
@@ -1109,20 +1331,71 @@ impl<'a> Parser<'a> {
     }
 
     fn consume_next(&mut self) -> Token<'a> {
-        self.scanner.next().unwrap()
+        let token = self.scanner.next().unwrap();
+        self.previous_end = token.span.end;
+        token
     }
 
-    fn create_comment_expr_box(&mut self, expr: Expr<'a>) -> ExprBox<'a> {
+    fn create_comment_expr_box(&mut self, start: usize, expr: Expr<'a>) -> ExprBox<'a> {
+        let span = Span::new(start, self.previous_end);
         Box::new(ExprBoxInterior {
             expr,
             trailing_comments: self.get_newlines_and_comments(),
+            span,
         })
     }
 
-    fn create_expr_box_no_comment(&self, expr: Expr<'a>) -> ExprBox<'a> {
+    fn create_expr_box_no_comment(&self, start: usize, expr: Expr<'a>) -> ExprBox<'a> {
         Box::new(ExprBoxInterior {
             expr,
             trailing_comments: None,
+            span: Span::new(start, self.previous_end),
         })
     }
 }
+
+#[cfg(test)]
+mod parser_test {
+    use super::*;
+
+    #[test]
+    fn spans() {
+        let source = "x = foo(1, 2) + 3; // note\nif (y) {\n    z++;\n}\n";
+        let ast = Parser::new(source).build_ast().unwrap();
+        let text = |span: Span| &source[span.start..span.end];
+
+        assert_eq!(text(ast[0].span), "x = foo(1, 2) + 3;");
+        if let Statement::ExpresssionStatement { expression } = &ast[0].statement {
+            assert_eq!(text(expression.span), "x = foo(1, 2) + 3");
+            if let Expr::Assign { left, right, .. } = &expression.expr {
+                assert_eq!(text(left.span), "x");
+                assert_eq!(text(right.span), "foo(1, 2) + 3");
+            } else {
+                panic!("expected an assignment");
+            }
+        } else {
+            panic!("expected an expression statement");
+        }
+
+        let if_statement = ast
+            .iter()
+            .find(|statement| matches!(statement.statement, Statement::If { .. }))
+            .unwrap();
+        assert_eq!(text(if_statement.span), "if (y) {\n    z++;\n}");
+    }
+
+    #[test]
+    fn verbatim_span() {
+        let source = "x = 1;\nswitch (a) {\n    ?? case 1: break;\n}\ny = 2;\n";
+        let ast = Parser::new(source).recover_from_errors().build_ast().unwrap();
+
+        let verbatim = ast
+            .iter()
+            .find(|statement| matches!(statement.statement, Statement::Verbatim(_)))
+            .unwrap();
+        assert_eq!(
+            &source[verbatim.span.start..verbatim.span.end],
+            "switch (a) {\n    ?? case 1: break;\n}"
+        );
+    }
+}
@@ -579,7 +579,9 @@ impl<'a> Printer<'a> {
                         self.print_statement(this_statement);
                     }
 
-                    self.backspace_till_newline();
+                    if self.backspace_till_newline() == false {
+                        self.print(NEWLINE, false);
+                    }
                     self.print_indentation_raw(saved_indentation);
                 }
 
@@ -595,6 +597,7 @@ impl<'a> Printer<'a> {
                 self.print_token(comment, false);
                 self.backspace();
             }
+            Statement::Verbatim(text) => self.print(text, false),
             Statement::Define {
                 comments_after_control_word,
                 script_name,
@@ -610,6 +613,25 @@ impl<'a> Printer<'a> {
                 self.backspace();
                 self.print_newline(IndentationMove::Stay);
 
+                for this_stmt in body {
+                    self.print_statement(this_stmt);
+                }
+            }
+            Statement::Header {
+                header,
+                blank_line_before,
+                body,
+            } => {
+                if *blank_line_before {
+                    let newlines = self.backspace_whitespace();
+                    for _ in 0..usize::max(newlines, 2) {
+                        self.print_newline(IndentationMove::Stay);
+                    }
+                }
+                self.print_token(header, false);
+                // the newline after the header is always ours to print.
+                self.do_not_print_single_newline_statement = false;
+
                 for this_stmt in body {
                     self.print_statement(this_stmt);
                 }
@@ -634,7 +656,9 @@ impl<'a> Printer<'a> {
                 | Statement::MultilineComment { .. }
                 | Statement::RegionBegin { .. }
                 | Statement::RegionEnd { .. }
-                | Statement::Macro { .. } => {}
+                | Statement::Macro { .. }
+                | Statement::Header { .. }
+                | Statement::Verbatim(_) => {}
 
                 _ => {
                     // we do this so we *always* print a newline.
@@ -1033,23 +1057,29 @@ impl<'a> Printer<'a> {
         false
     }
 
-    fn backspace_till_newline(&mut self) {
+    /// Removes the indentation after the last newline. Returns false when the line has more than
+    /// indentation on it, like a `Statement::Verbatim`, which never ends with its own newline, and
+    /// which we mustn't remove.
+    fn backspace_till_newline(&mut self) -> bool {
         let mut pos = self.output.len();
         if pos == 0 {
-            return;
+            return true;
         };
 
         pos -= 1;
 
         while pos != 0 {
             match self.output[pos] {
-                NEWLINE => break,
-                _ => {
+                NEWLINE => return true,
+                TAB | SPACE => {
                     self.output.remove(pos);
                     pos -= 1;
                 }
+                _ => return false,
             };
         }
+
+        true
     }
 
     fn backspace_whitespace(&mut self) -> usize {
@@ -1359,6 +1389,7 @@ impl<'a> Printer<'a> {
             TokenType::Newline(_) => "\n",
 
             TokenType::Macro(literal)
+            | TokenType::Header(literal)
             | TokenType::RegionBegin(literal)
             | TokenType::RegionEnd(literal)
             | TokenType::Identifier(literal)
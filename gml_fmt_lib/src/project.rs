@@ -0,0 +1,349 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::{ffi::OsStr, fs};
+
+/// What `.yyp` files call each object event, by its `eventtype`, and so the start of the
+/// name of the `.gml` file it lives in.
+pub(crate) const EVENT_NAMES: [&str; 15] = [
+    "Create",
+    "Destroy",
+    "Alarm",
+    "Step",
+    "Collision",
+    "Keyboard",
+    "Mouse",
+    "Other",
+    "Draw",
+    "KeyPress",
+    "KeyRelease",
+    "Trigger",
+    "CleanUp",
+    "Gesture",
+    "PreCreate",
+];
+
+/// The `.yyp` at the top of `directory`, if it holds a GameMaker project.
+pub(crate) fn find_yyp(directory: &Path) -> Result<Option<PathBuf>, &'static str> {
+    let mut found = None;
+
+    for entry in fs::read_dir(directory).map_err(|_| "Error reading directory.")? {
+        let path = entry.map_err(|_| "Error reading file")?.path();
+        if path.is_file() && path.extension() == Some(OsStr::new("yyp")) {
+            if found.is_some() {
+                return Err("Found more than one .yyp project in the directory. Pass the one to format.");
+            }
+            found = Some(path);
+        }
+    }
+
+    Ok(found)
+}
+
+/// Every `.gml` file which `yyp` owns: scripts, object events, room and instance creation code,
+/// and timeline moments. Included files and anything else lying around in the folder are left out.
+pub(crate) fn gml_files(yyp: &Path) -> Result<Vec<PathBuf>, &'static str> {
+    let project_directory = yyp.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut files = Vec::new();
+    for yy in yy_files(yyp)? {
+        let yy_contents = match fs::read_to_string(&yy) {
+            Ok(yy_contents) => yy_contents,
+            Err(_) => continue,
+        };
+
+        if let Ok(yy_json) = parse_json(&yy_contents) {
+            for file in owned_gml(project_directory, &yy, &yy_json) {
+                if file.is_file() && files.contains(&file) == false {
+                    files.push(file);
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// The `.yy` of every resource `yyp` lists. The project can list resources which are gone,
+/// and GameMaker shrugs at those, so we do too.
+pub(crate) fn yy_files(yyp: &Path) -> Result<Vec<PathBuf>, &'static str> {
+    let project_directory = yyp.parent().unwrap_or_else(|| Path::new(""));
+    let contents = fs::read_to_string(yyp).map_err(|_| "Error reading the .yyp project file.")?;
+
+    Ok(resources(&contents)?
+        .iter()
+        .map(|resource| project_directory.join(resource_path(resource)))
+        .filter(|yy| yy.is_file())
+        .collect())
+}
+
+/// The path of every resource's `.yy`, relative to the project, in the order the `.yyp` lists them.
+fn resources(yyp_contents: &str) -> Result<Vec<String>, &'static str> {
+    let yyp = parse_json(yyp_contents).map_err(|_| "Error parsing the .yyp project file.")?;
+    let resources = yyp["resources"]
+        .as_array()
+        .ok_or("The .yyp project file has no resources.")?;
+
+    Ok(resources
+        .iter()
+        .filter_map(|resource| {
+            // 2.2 projects say `{"Key": .., "Value": {"resourcePath": ..}}`, and 2.3 projects
+            // say `{"id": {"name": .., "path": ..}}`.
+            resource["Value"]["resourcePath"]
+                .as_str()
+                .or_else(|| resource["id"]["path"].as_str())
+                .map(str::to_owned)
+        })
+        .collect())
+}
+
+/// The `.gml` files which might belong to the resource described by `yy_json`, whether or not
+/// they're really there.
+fn owned_gml(project_directory: &Path, yy: &Path, yy_json: &Value) -> Vec<PathBuf> {
+    let resource_directory = yy.parent().unwrap_or(project_directory);
+    let resource_type = yy_json["resourceType"]
+        .as_str()
+        .or_else(|| yy_json["modelName"].as_str())
+        .unwrap_or_default();
+
+    match resource_type {
+        "GMScript" => vec![yy.with_extension("gml")],
+        "GMObject" => yy_json["eventList"]
+            .as_array()
+            .map(|events| {
+                events
+                    .iter()
+                    .filter_map(event_file_name)
+                    .map(|name| resource_directory.join(name))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        "GMRoom" => {
+            let mut files = Vec::new();
+            room_creation_code(project_directory, resource_directory, yy_json, &mut files);
+            files
+        }
+        "GMTimeline" => yy_json["momentList"]
+            .as_array()
+            .map(|moments| {
+                moments
+                    .iter()
+                    .filter_map(|moment| moment["moment"].as_u64())
+                    .map(|moment| resource_directory.join(format!("moment_{}.gml", moment)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Names an object event's file, like `Step_0.gml` or `Collision_obj_wall.gml`.
+fn event_file_name(event: &Value) -> Option<String> {
+    let event_type = event["eventtype"].as_u64().or_else(|| event["eventType"].as_u64())?;
+    let event_name = EVENT_NAMES.get(event_type as usize)?;
+
+    let suffix = if *event_name == "Collision" {
+        // 2.2 names collisions after the other object's id, and 2.3 after its name.
+        match &event["collisionObjectId"] {
+            Value::String(id) => id.clone(),
+            Value::Object(other) => other.get("name")?.as_str()?.to_owned(),
+            _ => return None,
+        }
+    } else {
+        event["enumb"]
+            .as_u64()
+            .or_else(|| event["eventNum"].as_u64())?
+            .to_string()
+    };
+
+    Some(format!("{}_{}.gml", event_name, suffix))
+}
+
+/// Finds the room's creation code, and its instances', anywhere in the room's layers.
+fn room_creation_code(project_directory: &Path, room_directory: &Path, value: &Value, files: &mut Vec<PathBuf>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(file) = object.get("creationCodeFile").and_then(Value::as_str) {
+                if file.is_empty() == false {
+                    let file = resource_path(file);
+                    // 2.2 gives a name in the room's folder, and 2.3 a path from the project.
+                    let directory = if file.components().count() > 1 {
+                        project_directory
+                    } else {
+                        room_directory
+                    };
+                    files.push(directory.join(file));
+                }
+            }
+            if object.get("hasCreationCode").and_then(Value::as_bool) == Some(true) {
+                if let Some(name) = object.get("name").and_then(Value::as_str) {
+                    files.push(room_directory.join(format!("InstanceCreationCode_{}.gml", name)));
+                }
+            }
+
+            for child in object.values() {
+                room_creation_code(project_directory, room_directory, child, files);
+            }
+        }
+        Value::Array(array) => {
+            for child in array {
+                room_creation_code(project_directory, room_directory, child, files);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// GameMaker writes paths with backslashes on every platform, at least before 2.3.
+pub(crate) fn resource_path(path: &str) -> PathBuf {
+    path.split(['/', '\\']).collect()
+}
+
+/// Parses a `.yy` or `.yyp`, which since 2.3 can have trailing commas that JSON doesn't allow.
+fn parse_json(contents: &str) -> serde_json::Result<Value> {
+    let mut json = String::with_capacity(contents.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut pending_comma = None;
+
+    for c in contents.trim_start_matches('\u{feff}').chars() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = escaped == false && c == '\\';
+            json.push(c);
+            continue;
+        }
+
+        match c {
+            ',' => {
+                if let Some(comma) = pending_comma.replace(String::new()) {
+                    json.push_str(&comma);
+                }
+                pending_comma.as_mut().unwrap().push(c);
+            }
+            c if c.is_whitespace() && pending_comma.is_some() => pending_comma.as_mut().unwrap().push(c),
+            '}' | ']' => {
+                // a comma right before the end of an object or array is dropped.
+                if let Some(comma) = pending_comma.take() {
+                    json.push_str(comma.trim_start_matches(','));
+                }
+                json.push(c);
+            }
+            _ => {
+                if let Some(comma) = pending_comma.take() {
+                    json.push_str(&comma);
+                }
+                in_string = c == '"';
+                json.push(c);
+            }
+        }
+    }
+    if let Some(comma) = pending_comma {
+        json.push_str(&comma);
+    }
+
+    serde_json::from_str(&json)
+}
+
+#[cfg(test)]
+mod project_test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn trailing_commas() {
+        assert_eq!(
+            parse_json("{\"a\": [1, 2,], \"b,\": \"}\\\",\",\n}").unwrap(),
+            json!({ "a": [1, 2], "b,": "}\",", })
+        );
+        assert!(parse_json("[1,,]").is_err());
+    }
+
+    #[test]
+    fn resources_of_both_versions() {
+        let gms_22 = r#"{
+            "resources": [
+                { "Key": "1", "Value": { "id": "2", "resourcePath": "scripts\\scr_a\\scr_a.yy", "resourceType": "GMScript" } }
+            ]
+        }"#;
+        let gms_23 = r#"{
+            "resources": [
+                {"id":{"name":"obj_player","path":"objects/obj_player/obj_player.yy",},"order":0,},
+            ],
+        }"#;
+
+        assert_eq!(resources(gms_22).unwrap(), vec!["scripts\\scr_a\\scr_a.yy"]);
+        assert_eq!(resources(gms_23).unwrap(), vec!["objects/obj_player/obj_player.yy"]);
+        assert!(resources("{}").is_err());
+    }
+
+    #[test]
+    fn owned_files() {
+        let project = Path::new("project");
+        let object = json!({
+            "modelName": "GMObject",
+            "eventList": [
+                { "eventtype": 0, "enumb": 0, "collisionObjectId": "00000000-0000-0000-0000-000000000000" },
+                { "eventType": 4, "eventNum": 0, "collisionObjectId": { "name": "obj_wall", "path": "objects/obj_wall/obj_wall.yy" } },
+                { "eventType": 7, "eventNum": 10, "collisionObjectId": null },
+            ],
+        });
+        assert_eq!(
+            owned_gml(project, &project.join("objects/obj_player/obj_player.yy"), &object),
+            vec![
+                project.join("objects/obj_player/Create_0.gml"),
+                project.join("objects/obj_player/Collision_obj_wall.gml"),
+                project.join("objects/obj_player/Other_10.gml"),
+            ]
+        );
+
+        let room = json!({
+            "resourceType": "GMRoom",
+            "creationCodeFile": "rooms/rm_start/RoomCreationCode.gml",
+            "layers": [{ "instances": [
+                { "name": "inst_1", "hasCreationCode": true },
+                { "name": "inst_2", "hasCreationCode": false },
+            ]}],
+        });
+        assert_eq!(
+            owned_gml(project, &project.join("rooms/rm_start/rm_start.yy"), &room),
+            vec![
+                project.join("rooms/rm_start/RoomCreationCode.gml"),
+                project.join("rooms/rm_start/InstanceCreationCode_inst_1.gml"),
+            ]
+        );
+
+        let included_file = json!({ "resourceType": "GMIncludedFile", "name": "notes.gml" });
+        assert!(owned_gml(project, &project.join("datafiles_yy/notes.gml.yy"), &included_file).is_empty());
+    }
+
+    #[test]
+    fn project_on_disk() {
+        let directory = std::env::temp_dir().join(format!("gml_fmt_project_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("scripts/scr_a")).unwrap();
+        fs::create_dir_all(directory.join("datafiles")).unwrap();
+
+        fs::write(
+            directory.join("game.yyp"),
+            r#"{"resources": [{"id": {"name": "scr_a", "path": "scripts/scr_a/scr_a.yy"}}, {"id": {"name": "scr_gone", "path": "scripts/scr_gone/scr_gone.yy"}}]}"#,
+        )
+        .unwrap();
+        fs::write(
+            directory.join("scripts/scr_a/scr_a.yy"),
+            r#"{"resourceType": "GMScript",}"#,
+        )
+        .unwrap();
+        fs::write(directory.join("scripts/scr_a/scr_a.gml"), "x = 1;\n").unwrap();
+        fs::write(directory.join("scripts/scr_a/scr_a_backup.gml"), "x = 1;\n").unwrap();
+        fs::write(directory.join("datafiles/notes.gml"), "x = 1;\n").unwrap();
+
+        let yyp = find_yyp(&directory).unwrap().unwrap();
+        assert_eq!(yyp, directory.join("game.yyp"));
+        assert_eq!(
+            gml_files(&yyp).unwrap(),
+            vec![directory.join("scripts/scr_a/scr_a.gml")]
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 use super::lex_token::*;
 use fnv::FnvHashMap;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use std::iter::Peekable;
 use std::str::CharIndices;
 
@@ -32,25 +33,48 @@ static KEYWORD_MAP: Lazy<FnvHashMap<&'static str, TokenType>> = Lazy::new(|| {
     map
 });
 
+/// Which version of GML we're reading. The two only differ in how strings are lexed.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    /// GameMaker Studio 2 and later, where strings have `\` escapes and end at the line's end.
+    #[default]
+    Gms2,
+    /// GameMaker: Studio 1.4, where a `\` is just a `\`, and strings run on over any number
+    /// of lines until their closing quote.
+    Gms1,
+}
+
+#[derive(Clone)]
 pub struct Scanner<'a> {
     input: &'a str,
+    token_start: usize,
     line_number: u32,
     column_number: u32,
     iter: Peekable<CharIndices<'a>>,
+    dialect: Dialect,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(input: &'a str) -> Scanner<'a> {
         Scanner {
             input,
+            token_start: 0,
             line_number: 0,
             column_number: 0,
             iter: input.char_indices().peekable(),
+            dialect: Dialect::Gms2,
         }
     }
 
+    pub fn with_dialect(mut self, dialect: Dialect) -> Scanner<'a> {
+        self.dialect = dialect;
+        self
+    }
+
     pub fn lex_input(&mut self) -> Option<Token<'a>> {
         while let Some((i, c)) = self.iter.next() {
+            self.token_start = i;
             let found_token = match c {
                 '(' => self.add_simple_token(TokenType::LeftParen),
                 ')' => self.add_simple_token(TokenType::RightParen),
@@ -264,6 +288,7 @@ impl<'a> Scanner<'a> {
 
                                     '\\' => {
                                         self.iter.next();
+                                        self.peek_and_check_consume('\r');
                                         if self.peek_and_check_consume('\n') {
                                             last_column_break = self.next_char_boundary();
                                             is_multiline = true;
@@ -276,7 +301,7 @@ impl<'a> Scanner<'a> {
                                     }
                                 }
                             }
-                            current = self.next_char_boundary();
+                            current = self.end_of_line(start);
                             Some(TokenType::Macro(&self.input[start..current]))
                         }
                         "#region" => {
@@ -288,7 +313,7 @@ impl<'a> Scanner<'a> {
                                     }
                                 }
                             }
-                            Some(TokenType::RegionBegin(&self.input[start..self.next_char_boundary()]))
+                            Some(TokenType::RegionBegin(&self.input[start..self.end_of_line(start)]))
                         }
                         "#endregion" => {
                             while let Some((_, peek_char)) = self.iter.peek() {
@@ -299,13 +324,25 @@ impl<'a> Scanner<'a> {
                                     }
                                 }
                             }
-                            Some(TokenType::RegionEnd(&self.input[start..self.next_char_boundary()]))
+                            Some(TokenType::RegionEnd(&self.input[start..self.end_of_line(start)]))
                         }
                         "#define" => Some(TokenType::Define),
+                        "#event" | "#section" | "#target" => {
+                            // trailing whitespace is left for the formatter to drop.
+                            let line = self.input[start..].split('\n').next().unwrap_or_default();
+                            let end = start + line.trim_end().len();
+                            while let Some((i, _)) = self.iter.peek() {
+                                if *i >= end {
+                                    break;
+                                }
+                                self.iter.next().unwrap();
+                            }
+                            Some(TokenType::Header(&self.input[start..end]))
+                        }
                         _ => None,
                     };
 
-                    current = self.next_char_boundary();
+                    current = self.end_of_line(start);
                     match token_returned {
                         Some(macro_directive) => {
                             if is_multiline {
@@ -335,17 +372,12 @@ impl<'a> Scanner<'a> {
                 // string literals
                 '@' => {
                     let start = i;
-                    let start_line = self.line_number;
-                    let start_column = self.column_number;
 
                     if let Some((_, this_char)) = self.iter.peek() {
                         match this_char {
                             '\'' | '\"' => {
                                 let (_, this_char) = self.iter.next().unwrap();
-                                let (current, last_column_break) = self.scan_multiline_string(start, this_char);
-
-                                self.column_number += (current - last_column_break) as u32;
-                                Token::new(TokenType::String(&self.input[start..current]), start_line, start_column)
+                                self.multiline_string(start, this_char)
                             }
 
                             _ => {
@@ -364,6 +396,7 @@ impl<'a> Scanner<'a> {
                         )
                     }
                 }
+                '"' | '\'' if self.dialect == Dialect::Gms1 => self.multiline_string(i, c),
                 '"' => {
                     let start = i;
                     let mut current = start;
@@ -549,7 +582,7 @@ impl<'a> Scanner<'a> {
                             }
                             self.iter.next();
                         }
-                        let current = self.next_char_boundary();
+                        let current = self.end_of_line(start);
 
                         self.add_multiple_token(
                             TokenType::Comment(&self.input[start..current]),
@@ -631,6 +664,7 @@ impl<'a> Scanner<'a> {
                 // Newline
                 '\n' => {
                     let mut tally = 0;
+                    let mut indentation_bytes = 0;
                     while let Some((_, c)) = self.iter.peek() {
                         match c {
                             ' ' => {
@@ -643,9 +677,12 @@ impl<'a> Scanner<'a> {
                             }
                             _ => break,
                         };
+                        indentation_bytes += 1;
                     }
-                    let ret = self.add_multiple_token(TokenType::Newline(tally / 4), tally as u32);
+                    let ret = self.add_simple_token(TokenType::Newline(tally / 4));
                     self.next_line();
+                    // the indentation we just ate still counts towards the next token's column.
+                    self.column_number = indentation_bytes;
                     ret
                 }
 
@@ -696,6 +733,16 @@ impl<'a> Scanner<'a> {
         self.column_number = 0;
     }
 
+    /// Lexes a string which can run over several lines, up to and including `break_char`.
+    fn multiline_string(&mut self, start: usize, break_char: char) -> Token<'a> {
+        let start_line = self.line_number;
+        let start_column = self.column_number;
+        let (current, last_column_break) = self.scan_multiline_string(start, break_char);
+
+        self.column_number += (current - last_column_break) as u32;
+        Token::new(TokenType::String(&self.input[start..current]), start_line, start_column)
+    }
+
     fn scan_multiline_string(&mut self, mut last_column_break: usize, break_char: char) -> (usize, usize) {
         while let Some((_, this_char)) = self.iter.next() {
             if this_char == break_char {
@@ -708,6 +755,17 @@ impl<'a> Scanner<'a> {
         (self.next_char_boundary(), last_column_break)
     }
 
+    /// The same as `next_char_boundary`, but leaves out the `\r` of a `\r\n` line ending,
+    /// for tokens which run to the end of their line.
+    fn end_of_line(&mut self, start: usize) -> usize {
+        let current = self.next_char_boundary();
+        if current > start && self.input[start..current].ends_with('\r') {
+            current - 1
+        } else {
+            current
+        }
+    }
+
     fn next_char_boundary(&mut self) -> usize {
         match self.iter.peek() {
             Some(_) => self.iter.peek().unwrap().0,
@@ -720,7 +778,18 @@ impl<'a> Iterator for Scanner<'a> {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.lex_input()
+        let mut token = self.lex_input()?;
+
+        let end = match token.token_type {
+            TokenType::Newline(_) => self.token_start + 1,
+            _ => {
+                let current = self.next_char_boundary();
+                self.token_start + self.input[self.token_start..current].trim_end_matches('\r').len()
+            }
+        };
+        token.span = Span::new(self.token_start, end);
+
+        Some(token)
     }
 }
 
@@ -729,6 +798,27 @@ mod scanner_test {
     use super::Scanner;
     use super::*;
 
+    /// Most of these tests are about lexing, so we leave spans to `token_spans`.
+    fn without_span(token: Token) -> Token {
+        Token {
+            span: Span::default(),
+            ..token
+        }
+    }
+
+    #[test]
+    fn token_spans() {
+        let input_string = "var xx = \"hi\";\r\n    // note\r\nxx+= 0.5";
+        let spans: Vec<&str> = Scanner::new(input_string)
+            .map(|token| &input_string[token.span.start..token.span.end])
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec!["var", "xx", "=", "\"hi\"", ";", "\n", "// note", "\n", "xx", "+=", "0.5"]
+        );
+    }
+
     #[test]
     fn lex_symbols<'a>() {
         let input_string = "(){}[] // grouping stuff
@@ -739,7 +829,7 @@ mod scanner_test {
 += -= *= /= ^= |= &= %= // set operators";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             vec,
             vec![
@@ -812,7 +902,7 @@ mod scanner_test {
 @\"This is a
 multi-linestring. The demon's plaything!\"";
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -833,6 +923,25 @@ multi-linestring. The demon's plaything!\"";
         );
     }
 
+    #[test]
+    fn lex_gms1_strings() {
+        let input_string = "\"C:\\\" + 'one\ntwo' + x";
+        let vec: Vec<Token> = Scanner::new(input_string)
+            .with_dialect(Dialect::Gms1)
+            .map(without_span)
+            .collect();
+        assert_eq!(
+            &vec,
+            &vec![
+                Token::new(TokenType::String("\"C:\\\""), 0, 0),
+                Token::new(TokenType::Plus, 0, 6),
+                Token::new(TokenType::String("'one\ntwo'"), 0, 8),
+                Token::new(TokenType::Plus, 1, 5),
+                Token::new(TokenType::Identifier("x"), 1, 7),
+            ]
+        );
+    }
+
     #[test]
     fn lex_numbers<'a>() {
         let input_string = "314159
@@ -845,7 +954,7 @@ multi-linestring. The demon's plaything!\"";
 .3";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -881,7 +990,7 @@ $ABCDEF
 $";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -917,7 +1026,7 @@ test_123
 testCase";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -943,7 +1052,7 @@ testCase";
         let input_string = "var and or if else return for repeat while do until switch case default div break enum";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -973,7 +1082,7 @@ testCase";
         let input_string = "and not or mod";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -990,7 +1099,7 @@ testCase";
         let input_string = "[ [? [# [| [@ ]";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -1013,7 +1122,7 @@ testCase";
 is bad";
 
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -1027,6 +1136,22 @@ is bad";
             ]
         )
     }
+    #[test]
+    fn lex_gmedit_headers() {
+        let input_string = "#event collision:obj_wall  \r\n#section#target windows";
+
+        let scanner = Scanner::new(input_string);
+        let vec: Vec<Token> = scanner.map(without_span).collect();
+        assert_eq!(
+            &vec,
+            &vec![
+                Token::new(TokenType::Header("#event collision:obj_wall"), 0, 0),
+                Token::new(TokenType::Newline(0), 0, 27),
+                Token::new(TokenType::Header("#section#target windows"), 1, 0),
+            ]
+        )
+    }
+
     #[test]
     fn lex_comments<'a>() {
         let input_string = "// normal comment
@@ -1036,7 +1161,7 @@ var x = a; // end comment
 liner comment
 */";
         let scanner = Scanner::new(input_string);
-        let vec: Vec<Token<'a>> = scanner.collect();
+        let vec: Vec<Token<'a>> = scanner.map(without_span).collect();
         assert_eq!(
             &vec,
             &vec![
@@ -1,5 +1,5 @@
 use super::expressions::*;
-use super::lex_token::Token;
+use super::lex_token::{Span, Token};
 
 pub type StmtBox<'a> = Box<StatementWrapper<'a>>;
 #[derive(Debug)]
@@ -9,9 +9,13 @@ pub struct DelimitedLines<'a, T> {
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct StatementWrapper<'a> {
     pub statement: Statement<'a>,
     pub has_semicolon: bool,
+    /// From the first token of this statement to its last, including its semicolon.
+    /// The Parser fills this in once it has the whole statement.
+    pub span: Span,
 }
 
 impl<'a> StatementWrapper<'a> {
@@ -19,6 +23,7 @@ impl<'a> StatementWrapper<'a> {
         Box::new(StatementWrapper {
             statement,
             has_semicolon,
+            span: Span::default(),
         })
     }
 
@@ -32,6 +37,7 @@ impl<'a> StatementWrapper<'a> {
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Statement<'a> {
     VariableDeclList {
         starting_var_type: Token<'a>,
@@ -107,6 +113,16 @@ pub enum Statement<'a> {
         script_name: ExprBox<'a>,
         body: Vec<StmtBox<'a>>,
     },
+    /// A GMEdit header, like `#event step`, and the statements up to the next header or `#define`.
+    /// `blank_line_before` is set when the line above the header was empty, since those newlines
+    /// belong to the statement before it, which doesn't always print them.
+    Header {
+        header: Token<'a>,
+        blank_line_before: bool,
+        body: Vec<StmtBox<'a>>,
+    },
+    /// Code we couldn't parse, which is printed back out exactly as the user wrote it.
+    Verbatim(&'a str),
 }
 
 #[derive(Debug)]
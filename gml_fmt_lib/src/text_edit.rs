@@ -0,0 +1,262 @@
+use super::diff;
+use super::error::FormatResult;
+use super::lang_config::LangConfig;
+use super::lex_token::Span;
+
+/// One replacement to make to a source: the bytes in `span` become `new_text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub new_text: String,
+}
+
+/// Formats `source`, and returns the edits which turn it into the formatted code, rather than
+/// the formatted code itself. Spans are byte offsets into `source`, and the edits are in order
+/// and never overlap.
+pub fn format_to_edits(source: &str, lang_config: &LangConfig) -> FormatResult<Vec<TextEdit>> {
+    let formatted = super::run(source, lang_config, None)?;
+    Ok(edits_between(source, &formatted))
+}
+
+/// The edits which turn `old` into `new`. We find the lines which changed first, and then
+/// the words, spaces and symbols which changed within them, so that the edits stay small.
+pub fn edits_between(old: &str, new: &str) -> Vec<TextEdit> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let old_offsets = offsets(&old_lines);
+    let new_offsets = offsets(&new_lines);
+
+    let mut edits = Vec::new();
+    for change in diff::diff(&old_lines, &new_lines) {
+        let old_start = old_offsets[change.old_start];
+        let new_start = new_offsets[change.new_start];
+        let old_pieces = pieces(&old[old_start..old_offsets[change.old_start + change.old_len]]);
+        let new_pieces = pieces(&new[new_start..new_offsets[change.new_start + change.new_len]]);
+        let old_piece_offsets = offsets(&old_pieces);
+
+        for piece_change in diff::diff(&old_pieces, &new_pieces) {
+            edits.push(TextEdit {
+                span: Span::new(
+                    old_start + old_piece_offsets[piece_change.old_start],
+                    old_start + old_piece_offsets[piece_change.old_start + piece_change.old_len],
+                ),
+                new_text: new_pieces[piece_change.new_start..piece_change.new_start + piece_change.new_len].concat(),
+            });
+        }
+    }
+
+    edits
+}
+
+/// Formats `source` like `format_to_edits`, and also works out where each of `cursors` (byte offsets
+/// into `source`, like a caret or the ends of a selection) ends up in the formatted code.
+pub fn format_with_cursors(
+    source: &str,
+    lang_config: &LangConfig,
+    cursors: &[usize],
+) -> FormatResult<(String, Vec<usize>)> {
+    let formatted = super::run(source, lang_config, None)?;
+    let edits = edits_between(source, &formatted);
+    let cursors = cursors
+        .iter()
+        .map(|cursor| map_offset(source, &edits, *cursor))
+        .collect();
+
+    Ok((formatted, cursors))
+}
+
+/// Where `offset` into `source` ends up once `edits` are made. An offset past the end, or in the
+/// middle of a character, is moved back to the nearest place a cursor could be.
+pub fn map_offset(source: &str, edits: &[TextEdit], offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let mut shift = 0isize;
+
+    for edit in edits {
+        let new_start = (edit.span.start as isize + shift) as usize;
+
+        if edit.span.start == offset && edit.span.start == edit.span.end {
+            // text inserted right at the cursor goes after it, unless it's the indentation
+            // in front of the line the cursor is at the start of.
+            let at_line_start = source[..offset].ends_with('\n') || offset == 0;
+            if at_line_start && edit.new_text.trim().is_empty() {
+                return new_start + edit.new_text.len();
+            }
+            return new_start;
+        }
+        if offset < edit.span.start {
+            break;
+        }
+        if offset < edit.span.end {
+            // inside text which was replaced, so we stay as far in as the new text lets us.
+            let mut within = usize::min(offset - edit.span.start, edit.new_text.len());
+            while !edit.new_text.is_char_boundary(within) {
+                within -= 1;
+            }
+            return new_start + within;
+        }
+
+        shift += edit.new_text.len() as isize - (edit.span.end - edit.span.start) as isize;
+    }
+
+    (offset as isize + shift) as usize
+}
+
+/// Makes `edits` to `source`, which is handy for anyone holding a copy of the source
+/// and a list of edits from `format_to_edits`.
+pub fn apply_edits(source: &str, edits: &[TextEdit]) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut position = 0;
+
+    for edit in edits {
+        output.push_str(&source[position..edit.span.start]);
+        output.push_str(&edit.new_text);
+        position = edit.span.end;
+    }
+    output.push_str(&source[position..]);
+
+    output
+}
+
+/// Where each of `pieces` starts, plus where the last one ends.
+fn offsets(pieces: &[&str]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(pieces.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for piece in pieces {
+        offset += piece.len();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// Splits text into runs of word characters, runs of spaces and tabs, and single characters otherwise.
+fn pieces(text: &str) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            1
+        } else if c == ' ' || c == '\t' {
+            2
+        } else {
+            0
+        }
+    };
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let this_class = class(c);
+        while let Some((_, next)) = chars.peek() {
+            if this_class == 0 || class(*next) != this_class {
+                break;
+            }
+            chars.next();
+        }
+
+        let end = chars.peek().map_or(text.len(), |(i, _)| *i);
+        pieces.push(&text[start..end]);
+        start = end;
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod text_edit_test {
+    use super::*;
+
+    #[test]
+    fn small_edits() {
+        let source = "x=1\nif (a) {\ny = foo;\nz();\n}\n";
+        let edits = format_to_edits(source, &LangConfig::default()).unwrap();
+
+        assert_eq!(
+            edits,
+            vec![
+                TextEdit {
+                    span: Span::new(1, 1),
+                    new_text: " ".to_owned(),
+                },
+                TextEdit {
+                    span: Span::new(2, 2),
+                    new_text: " ".to_owned(),
+                },
+                TextEdit {
+                    span: Span::new(3, 3),
+                    new_text: ";".to_owned(),
+                },
+                TextEdit {
+                    span: Span::new(13, 13),
+                    new_text: "    ".to_owned(),
+                },
+                TextEdit {
+                    span: Span::new(22, 22),
+                    new_text: "    ".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            apply_edits(source, &edits),
+            "x = 1;\nif (a) {\n    y = foo;\n    z();\n}\n"
+        );
+    }
+
+    #[test]
+    fn offsets_inside_characters() {
+        let source = "s=\"é\"\n";
+        let edits = format_to_edits(source, &LangConfig::default()).unwrap();
+        // the middle of `é` counts as its start.
+        assert_eq!(map_offset(source, &edits, 4), map_offset(source, &edits, 3));
+        assert_eq!(map_offset(source, &edits, 100), "s = \"é\";\n".len());
+    }
+
+    #[test]
+    fn large_changes_stay_small() {
+        let old: String = (0..3000).map(|i| format!("v{}={}\n", i, i)).collect();
+        let new: String = (0..3000).map(|i| format!("v{} = {};\n", i, i)).collect();
+        let edits = edits_between(&old, &new);
+
+        assert_eq!(apply_edits(&old, &edits), new);
+        let largest = edits
+            .iter()
+            .map(|edit| edit.span.end - edit.span.start + edit.new_text.len())
+            .max();
+        assert!(largest < Some(16), "{:?}", largest);
+    }
+
+    #[test]
+    fn no_edits() {
+        let source = "x = 1;\n";
+        assert!(format_to_edits(source, &LangConfig::default()).unwrap().is_empty());
+        assert!(format_to_edits("x = (", &LangConfig::default()).is_err());
+    }
+
+    #[test]
+    fn cursors() {
+        let source = "x=1\nif (a) {\ny = foo;\nz();\n}\n";
+        let (formatted, cursors) =
+            format_with_cursors(source, &LangConfig::default(), &[1, 3, 13, 18, 25, 99]).unwrap();
+
+        assert_eq!(formatted, "x = 1;\nif (a) {\n    y = foo;\n    z();\n}\n");
+        // after `x`, after `1`, before `y`, before `foo`, before the last `;` and past the end.
+        assert_eq!(cursors, vec![1, 5, 20, 25, 36, formatted.len()]);
+    }
+
+    #[test]
+    fn cursors_in_replaced_text() {
+        let source = "a = .5;";
+        let edits = edits_between(source, "a = 0.5;");
+        assert_eq!(map_offset(source, &edits, 4), 4);
+        assert_eq!(map_offset(source, &edits, 5), 6);
+    }
+
+    #[test]
+    fn round_trip() {
+        let old = "a b\tc\n\nd(e, f)\r\nwhat";
+        let new = "a  c\nd (e,f)\n\nwhat?\n";
+        assert_eq!(apply_edits(old, &edits_between(old, new)), new);
+    }
+}
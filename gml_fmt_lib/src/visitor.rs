@@ -0,0 +1,718 @@
+use super::ast::Ast;
+use super::expressions::*;
+use super::lex_token::Token;
+use super::statements::*;
+
+/// Walks an `Ast` in source order. Every method does nothing but walk on by default,
+/// so override the ones you care about, and call the matching `walk_` function from
+/// your override if you still want to visit what's underneath.
+pub trait Visitor<'a> {
+    fn visit_statement(&mut self, statement: &StatementWrapper<'a>) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_expr(&mut self, expr: &ExprBoxInterior<'a>) {
+        walk_expr(self, expr);
+    }
+
+    /// Called for every comment, newline and region marker kept alongside the tree.
+    fn visit_comments(&mut self, comments: &CommentsAndNewlines<'a>) {
+        walk_comments(self, comments);
+    }
+
+    fn visit_token(&mut self, _token: &Token<'a>) {}
+}
+
+/// Like `Visitor`, but with mutable access to the tree, so that it can be rewritten in place.
+pub trait VisitorMut<'a> {
+    fn visit_statement_mut(&mut self, statement: &mut StatementWrapper<'a>) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut ExprBoxInterior<'a>) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_comments_mut(&mut self, comments: &mut CommentsAndNewlines<'a>) {
+        walk_comments_mut(self, comments);
+    }
+
+    fn visit_token_mut(&mut self, _token: &mut Token<'a>) {}
+}
+
+pub fn walk_ast<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, ast: &Ast<'a>) {
+    for statement in &ast.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, statement: &StatementWrapper<'a>) {
+    match &statement.statement {
+        Statement::VariableDeclList {
+            starting_var_type,
+            comments_after_control_word,
+            var_decl,
+        } => {
+            visitor.visit_token(starting_var_type);
+            visitor.visit_comments(comments_after_control_word);
+            for line in &var_decl.lines {
+                if let Some(say_var) = &line.expr.say_var {
+                    visitor.visit_token(say_var);
+                }
+                if let Some(say_var_comments) = &line.expr.say_var_comments {
+                    visitor.visit_comments(say_var_comments);
+                }
+                visitor.visit_expr(&line.expr.var_expr);
+                visitor.visit_comments(&line.trailing_comment);
+            }
+        }
+        Statement::EnumDeclaration {
+            comments_after_control_word,
+            name,
+            comments_after_lbrace,
+            members,
+        } => {
+            visitor.visit_comments(comments_after_control_word);
+            visitor.visit_expr(name);
+            visitor.visit_comments(comments_after_lbrace);
+            walk_delimited_lines(visitor, members);
+        }
+        Statement::If {
+            comments_after_control_word,
+            condition,
+            then_branch,
+            comments_between,
+            else_branch,
+        } => {
+            visitor.visit_comments(comments_after_control_word);
+            visitor.visit_expr(condition);
+            visitor.visit_statement(then_branch);
+            visitor.visit_comments(comments_between);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_statement(else_branch);
+            }
+        }
+        Statement::WhileWithRepeat {
+            comments_after_control_word,
+            token,
+            condition,
+            body,
+        } => {
+            visitor.visit_token(token);
+            visitor.visit_comments(comments_after_control_word);
+            visitor.visit_expr(condition);
+            visitor.visit_statement(body);
+        }
+        Statement::DoUntil {
+            comments_after_control_word,
+            body,
+            comments_between,
+            condition,
+        } => {
+            visitor.visit_comments(comments_after_control_word);
+            visitor.visit_statement(body);
+            visitor.visit_comments(comments_between);
+            visitor.visit_expr(condition);
+        }
+        Statement::For {
+            comments_after_control_word,
+            comments_after_lparen,
+            initializer,
+            comments_after_initializer,
+            condition,
+            comments_after_condition,
+            increment,
+            comments_after_increment,
+            comments_after_rparen,
+            body,
+        } => {
+            visitor.visit_comments(comments_after_control_word);
+            visitor.visit_comments(comments_after_lparen);
+            if let Some(initializer) = initializer {
+                visitor.visit_statement(initializer);
+            }
+            visitor.visit_comments(comments_after_initializer);
+            if let Some(condition) = condition {
+                visitor.visit_expr(condition);
+            }
+            visitor.visit_comments(comments_after_condition);
+            if let Some(increment) = increment {
+                visitor.visit_expr(increment);
+            }
+            visitor.visit_comments(comments_after_increment);
+            visitor.visit_comments(comments_after_rparen);
+            visitor.visit_statement(body);
+        }
+        Statement::Switch {
+            comments_after_control_word,
+            condition,
+            comments_after_lbrace,
+            cases,
+        } => {
+            visitor.visit_comments(comments_after_control_word);
+            visitor.visit_expr(condition);
+            visitor.visit_comments(comments_after_lbrace);
+            for case in cases {
+                visitor.visit_comments(&case.comments_after_control_word);
+                if let CaseType::Case(constant) = &case.control_word {
+                    visitor.visit_expr(constant);
+                }
+                visitor.visit_comments(&case.comments_after_colon);
+                for statement in &case.statements {
+                    visitor.visit_statement(statement);
+                }
+            }
+        }
+        Statement::ExpresssionStatement { expression } => visitor.visit_expr(expression),
+        Statement::Block {
+            comments_after_lbrace,
+            statements,
+        } => {
+            visitor.visit_comments(comments_after_lbrace);
+            for statement in statements {
+                visitor.visit_statement(statement);
+            }
+        }
+        Statement::Return { expression } => {
+            if let Some(expression) = expression {
+                visitor.visit_expr(expression);
+            }
+        }
+        Statement::Break | Statement::Exit | Statement::Verbatim(_) => {}
+        Statement::Comment { comment: token }
+        | Statement::MultilineComment {
+            multiline_comment: token,
+        }
+        | Statement::RegionBegin(token)
+        | Statement::RegionEnd(token)
+        | Statement::Macro(token) => visitor.visit_token(token),
+        Statement::Define {
+            comments_after_control_word,
+            script_name,
+            body,
+        } => {
+            visitor.visit_comments(comments_after_control_word);
+            visitor.visit_expr(script_name);
+            for statement in body {
+                visitor.visit_statement(statement);
+            }
+        }
+        Statement::Header { header, body, .. } => {
+            visitor.visit_token(header);
+            for statement in body {
+                visitor.visit_statement(statement);
+            }
+        }
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &ExprBoxInterior<'a>) {
+    match &expr.expr {
+        Expr::Call {
+            procedure_name,
+            comments_and_newlines_after_lparen,
+            arguments,
+        } => {
+            visitor.visit_expr(procedure_name);
+            visitor.visit_comments(comments_and_newlines_after_lparen);
+            walk_delimited_lines(visitor, arguments);
+        }
+        Expr::Binary {
+            left,
+            operator,
+            comments_and_newlines_between_op_and_r,
+            right,
+        }
+        | Expr::Assign {
+            left,
+            operator,
+            comments_and_newlines_between_op_and_r,
+            right,
+        } => {
+            visitor.visit_expr(left);
+            visitor.visit_token(operator);
+            visitor.visit_comments(comments_and_newlines_between_op_and_r);
+            visitor.visit_expr(right);
+        }
+        Expr::Grouping {
+            comments_and_newlines_after_lparen,
+            expressions,
+            comments_and_newlines_after_rparen,
+        } => {
+            visitor.visit_comments(comments_and_newlines_after_lparen);
+            for expression in expressions {
+                visitor.visit_expr(expression);
+            }
+            visitor.visit_comments(comments_and_newlines_after_rparen);
+        }
+        Expr::ArrayLiteral {
+            comments_and_newlines_after_lbracket,
+            arguments,
+        } => {
+            visitor.visit_comments(comments_and_newlines_after_lbracket);
+            walk_delimited_lines(visitor, arguments);
+        }
+        Expr::Literal {
+            literal_token: token,
+            comments,
+        }
+        | Expr::NumberStartDot {
+            literal_token: token,
+            comments,
+        }
+        | Expr::NumberEndDot {
+            literal_token: token,
+            comments,
+        }
+        | Expr::Identifier { name: token, comments } => {
+            visitor.visit_token(token);
+            visitor.visit_comments(comments);
+        }
+        Expr::Unary {
+            operator,
+            comments_and_newlines_between,
+            right,
+        } => {
+            visitor.visit_token(operator);
+            visitor.visit_comments(comments_and_newlines_between);
+            visitor.visit_expr(right);
+        }
+        Expr::Postfix {
+            operator,
+            comments_and_newlines_between,
+            expr,
+        } => {
+            visitor.visit_expr(expr);
+            visitor.visit_token(operator);
+            visitor.visit_comments(comments_and_newlines_between);
+        }
+        Expr::DotAccess {
+            object_name,
+            comments_between,
+            instance_variable,
+        } => {
+            visitor.visit_expr(object_name);
+            visitor.visit_comments(comments_between);
+            visitor.visit_expr(instance_variable);
+        }
+        Expr::DataStructureAccess {
+            ds_name,
+            access_type,
+            access_exprs,
+        } => {
+            visitor.visit_expr(ds_name);
+            visitor.visit_token(access_type);
+            for (comments, access_expr) in access_exprs {
+                visitor.visit_comments(comments);
+                visitor.visit_expr(access_expr);
+            }
+        }
+        Expr::Ternary {
+            conditional,
+            comments_and_newlines_after_q,
+            left,
+            comments_and_newlines_after_colon,
+            right,
+        } => {
+            visitor.visit_expr(conditional);
+            visitor.visit_comments(comments_and_newlines_after_q);
+            visitor.visit_expr(left);
+            visitor.visit_comments(comments_and_newlines_after_colon);
+            visitor.visit_expr(right);
+        }
+        Expr::Newline => {}
+        Expr::Comment { comment: token }
+        | Expr::MultilineComment {
+            multiline_comment: token,
+        }
+        | Expr::UnidentifiedAsLiteral { literal_token: token } => visitor.visit_token(token),
+    }
+
+    visitor.visit_comments(&expr.trailing_comments);
+}
+
+pub fn walk_comments<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, comments: &CommentsAndNewlines<'a>) {
+    if let Some(comments) = comments {
+        for token in comments {
+            visitor.visit_token(token);
+        }
+    }
+}
+
+fn walk_delimited_lines<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, lines: &DelimitedLines<'a, ExprBox<'a>>) {
+    for line in &lines.lines {
+        visitor.visit_expr(&line.expr);
+        visitor.visit_comments(&line.trailing_comment);
+    }
+}
+
+pub fn walk_ast_mut<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, ast: &mut Ast<'a>) {
+    for statement in &mut ast.statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, statement: &mut StatementWrapper<'a>) {
+    match &mut statement.statement {
+        Statement::VariableDeclList {
+            starting_var_type,
+            comments_after_control_word,
+            var_decl,
+        } => {
+            visitor.visit_token_mut(starting_var_type);
+            visitor.visit_comments_mut(comments_after_control_word);
+            for line in &mut var_decl.lines {
+                if let Some(say_var) = &mut line.expr.say_var {
+                    visitor.visit_token_mut(say_var);
+                }
+                if let Some(say_var_comments) = &mut line.expr.say_var_comments {
+                    visitor.visit_comments_mut(say_var_comments);
+                }
+                visitor.visit_expr_mut(&mut line.expr.var_expr);
+                visitor.visit_comments_mut(&mut line.trailing_comment);
+            }
+        }
+        Statement::EnumDeclaration {
+            comments_after_control_word,
+            name,
+            comments_after_lbrace,
+            members,
+        } => {
+            visitor.visit_comments_mut(comments_after_control_word);
+            visitor.visit_expr_mut(name);
+            visitor.visit_comments_mut(comments_after_lbrace);
+            walk_delimited_lines_mut(visitor, members);
+        }
+        Statement::If {
+            comments_after_control_word,
+            condition,
+            then_branch,
+            comments_between,
+            else_branch,
+        } => {
+            visitor.visit_comments_mut(comments_after_control_word);
+            visitor.visit_expr_mut(condition);
+            visitor.visit_statement_mut(then_branch);
+            visitor.visit_comments_mut(comments_between);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_statement_mut(else_branch);
+            }
+        }
+        Statement::WhileWithRepeat {
+            comments_after_control_word,
+            token,
+            condition,
+            body,
+        } => {
+            visitor.visit_token_mut(token);
+            visitor.visit_comments_mut(comments_after_control_word);
+            visitor.visit_expr_mut(condition);
+            visitor.visit_statement_mut(body);
+        }
+        Statement::DoUntil {
+            comments_after_control_word,
+            body,
+            comments_between,
+            condition,
+        } => {
+            visitor.visit_comments_mut(comments_after_control_word);
+            visitor.visit_statement_mut(body);
+            visitor.visit_comments_mut(comments_between);
+            visitor.visit_expr_mut(condition);
+        }
+        Statement::For {
+            comments_after_control_word,
+            comments_after_lparen,
+            initializer,
+            comments_after_initializer,
+            condition,
+            comments_after_condition,
+            increment,
+            comments_after_increment,
+            comments_after_rparen,
+            body,
+        } => {
+            visitor.visit_comments_mut(comments_after_control_word);
+            visitor.visit_comments_mut(comments_after_lparen);
+            if let Some(initializer) = initializer {
+                visitor.visit_statement_mut(initializer);
+            }
+            visitor.visit_comments_mut(comments_after_initializer);
+            if let Some(condition) = condition {
+                visitor.visit_expr_mut(condition);
+            }
+            visitor.visit_comments_mut(comments_after_condition);
+            if let Some(increment) = increment {
+                visitor.visit_expr_mut(increment);
+            }
+            visitor.visit_comments_mut(comments_after_increment);
+            visitor.visit_comments_mut(comments_after_rparen);
+            visitor.visit_statement_mut(body);
+        }
+        Statement::Switch {
+            comments_after_control_word,
+            condition,
+            comments_after_lbrace,
+            cases,
+        } => {
+            visitor.visit_comments_mut(comments_after_control_word);
+            visitor.visit_expr_mut(condition);
+            visitor.visit_comments_mut(comments_after_lbrace);
+            for case in cases {
+                visitor.visit_comments_mut(&mut case.comments_after_control_word);
+                if let CaseType::Case(constant) = &mut case.control_word {
+                    visitor.visit_expr_mut(constant);
+                }
+                visitor.visit_comments_mut(&mut case.comments_after_colon);
+                for statement in &mut case.statements {
+                    visitor.visit_statement_mut(statement);
+                }
+            }
+        }
+        Statement::ExpresssionStatement { expression } => visitor.visit_expr_mut(expression),
+        Statement::Block {
+            comments_after_lbrace,
+            statements,
+        } => {
+            visitor.visit_comments_mut(comments_after_lbrace);
+            for statement in statements {
+                visitor.visit_statement_mut(statement);
+            }
+        }
+        Statement::Return { expression } => {
+            if let Some(expression) = expression {
+                visitor.visit_expr_mut(expression);
+            }
+        }
+        Statement::Break | Statement::Exit | Statement::Verbatim(_) => {}
+        Statement::Comment { comment: token }
+        | Statement::MultilineComment {
+            multiline_comment: token,
+        }
+        | Statement::RegionBegin(token)
+        | Statement::RegionEnd(token)
+        | Statement::Macro(token) => visitor.visit_token_mut(token),
+        Statement::Define {
+            comments_after_control_word,
+            script_name,
+            body,
+        } => {
+            visitor.visit_comments_mut(comments_after_control_word);
+            visitor.visit_expr_mut(script_name);
+            for statement in body {
+                visitor.visit_statement_mut(statement);
+            }
+        }
+        Statement::Header { header, body, .. } => {
+            visitor.visit_token_mut(header);
+            for statement in body {
+                visitor.visit_statement_mut(statement);
+            }
+        }
+    }
+}
+
+pub fn walk_expr_mut<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, expr: &mut ExprBoxInterior<'a>) {
+    match &mut expr.expr {
+        Expr::Call {
+            procedure_name,
+            comments_and_newlines_after_lparen,
+            arguments,
+        } => {
+            visitor.visit_expr_mut(procedure_name);
+            visitor.visit_comments_mut(comments_and_newlines_after_lparen);
+            walk_delimited_lines_mut(visitor, arguments);
+        }
+        Expr::Binary {
+            left,
+            operator,
+            comments_and_newlines_between_op_and_r,
+            right,
+        }
+        | Expr::Assign {
+            left,
+            operator,
+            comments_and_newlines_between_op_and_r,
+            right,
+        } => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_token_mut(operator);
+            visitor.visit_comments_mut(comments_and_newlines_between_op_and_r);
+            visitor.visit_expr_mut(right);
+        }
+        Expr::Grouping {
+            comments_and_newlines_after_lparen,
+            expressions,
+            comments_and_newlines_after_rparen,
+        } => {
+            visitor.visit_comments_mut(comments_and_newlines_after_lparen);
+            for expression in expressions {
+                visitor.visit_expr_mut(expression);
+            }
+            visitor.visit_comments_mut(comments_and_newlines_after_rparen);
+        }
+        Expr::ArrayLiteral {
+            comments_and_newlines_after_lbracket,
+            arguments,
+        } => {
+            visitor.visit_comments_mut(comments_and_newlines_after_lbracket);
+            walk_delimited_lines_mut(visitor, arguments);
+        }
+        Expr::Literal {
+            literal_token: token,
+            comments,
+        }
+        | Expr::NumberStartDot {
+            literal_token: token,
+            comments,
+        }
+        | Expr::NumberEndDot {
+            literal_token: token,
+            comments,
+        }
+        | Expr::Identifier { name: token, comments } => {
+            visitor.visit_token_mut(token);
+            visitor.visit_comments_mut(comments);
+        }
+        Expr::Unary {
+            operator,
+            comments_and_newlines_between,
+            right,
+        } => {
+            visitor.visit_token_mut(operator);
+            visitor.visit_comments_mut(comments_and_newlines_between);
+            visitor.visit_expr_mut(right);
+        }
+        Expr::Postfix {
+            operator,
+            comments_and_newlines_between,
+            expr,
+        } => {
+            visitor.visit_expr_mut(expr);
+            visitor.visit_token_mut(operator);
+            visitor.visit_comments_mut(comments_and_newlines_between);
+        }
+        Expr::DotAccess {
+            object_name,
+            comments_between,
+            instance_variable,
+        } => {
+            visitor.visit_expr_mut(object_name);
+            visitor.visit_comments_mut(comments_between);
+            visitor.visit_expr_mut(instance_variable);
+        }
+        Expr::DataStructureAccess {
+            ds_name,
+            access_type,
+            access_exprs,
+        } => {
+            visitor.visit_expr_mut(ds_name);
+            visitor.visit_token_mut(access_type);
+            for (comments, access_expr) in access_exprs {
+                visitor.visit_comments_mut(comments);
+                visitor.visit_expr_mut(access_expr);
+            }
+        }
+        Expr::Ternary {
+            conditional,
+            comments_and_newlines_after_q,
+            left,
+            comments_and_newlines_after_colon,
+            right,
+        } => {
+            visitor.visit_expr_mut(conditional);
+            visitor.visit_comments_mut(comments_and_newlines_after_q);
+            visitor.visit_expr_mut(left);
+            visitor.visit_comments_mut(comments_and_newlines_after_colon);
+            visitor.visit_expr_mut(right);
+        }
+        Expr::Newline => {}
+        Expr::Comment { comment: token }
+        | Expr::MultilineComment {
+            multiline_comment: token,
+        }
+        | Expr::UnidentifiedAsLiteral { literal_token: token } => visitor.visit_token_mut(token),
+    }
+
+    visitor.visit_comments_mut(&mut expr.trailing_comments);
+}
+
+pub fn walk_comments_mut<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, comments: &mut CommentsAndNewlines<'a>) {
+    if let Some(comments) = comments {
+        for token in comments {
+            visitor.visit_token_mut(token);
+        }
+    }
+}
+
+fn walk_delimited_lines_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    lines: &mut DelimitedLines<'a, ExprBox<'a>>,
+) {
+    for line in &mut lines.lines {
+        visitor.visit_expr_mut(&mut line.expr);
+        visitor.visit_comments_mut(&mut line.trailing_comment);
+    }
+}
+
+#[cfg(test)]
+mod visitor_test {
+    use super::*;
+    use crate::ast::{format_ast, parse};
+    use crate::lang_config::LangConfig;
+    use crate::lex_token::TokenType;
+
+    #[derive(Default)]
+    struct Collector<'a> {
+        identifiers: Vec<&'a str>,
+        comments: Vec<&'a str>,
+    }
+
+    impl<'a> Visitor<'a> for Collector<'a> {
+        fn visit_token(&mut self, token: &Token<'a>) {
+            match token.token_type {
+                TokenType::Identifier(name) => self.identifiers.push(name),
+                TokenType::Comment(comment) => self.comments.push(comment),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn visits_in_source_order() {
+        let ast = parse(
+            "var a = b; // one\nswitch (c) {\n    case d: // two\n        e[f] = g ? h : i;\n}\nfor (j = 0; j < k; j++) { l(m, /* three */ n); }\n",
+        )
+        .unwrap();
+
+        let mut collector = Collector::default();
+        walk_ast(&mut collector, &ast);
+
+        assert_eq!(
+            collector.identifiers,
+            vec!["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "j", "k", "j", "l", "m", "n"]
+        );
+        assert_eq!(collector.comments, vec!["// one", "// two"]);
+    }
+
+    struct Renamer;
+
+    impl<'a> VisitorMut<'a> for Renamer {
+        fn visit_token_mut(&mut self, token: &mut Token<'a>) {
+            if token.token_type == TokenType::Identifier("old_name") {
+                token.token_type = TokenType::Identifier("new_name");
+            }
+        }
+    }
+
+    #[test]
+    fn rewrites_in_place() {
+        let mut ast = parse("if (old_name > 2) {\nold_name(old_name.x)\ny = old_name;\n}").unwrap();
+        walk_ast_mut(&mut Renamer, &mut ast);
+
+        assert_eq!(
+            format_ast(&ast, &LangConfig::default()),
+            "if (new_name > 2) {\n    new_name(new_name.x);\n    y = new_name;\n}\n"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use super::diagnostics::{Diagnostic, Position, Severity};
+use super::lang_config::LangConfig;
+use super::line_endings::{self, SourceLayout};
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Whether `path` is a resource or project file for `format_yy`.
+pub(crate) fn is_yy(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("yy")) || path.extension() == Some(OsStr::new("yyp"))
+}
+
+/// A `.yy` value, holding on to the text of every string, number and literal exactly as it
+/// was written, and the order of every object's keys.
+#[derive(Debug, PartialEq)]
+enum Json<'a> {
+    Object(Vec<(&'a str, Json<'a>)>),
+    Array(Vec<Json<'a>>),
+    Scalar(&'a str),
+}
+
+/// Why a `.yy` couldn't be read, and the byte offset where we gave up.
+#[derive(Debug, PartialEq)]
+pub(crate) struct YyError {
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+/// Lays a 2.3+ `.yy` or `.yyp` out the way the IDE writes it: two space indentation, a comma
+/// after every member and element, and every object inside an array on one line. 2023 and later
+/// files have their keys sorted, since that IDE writes them in ASCII order. Before that, every
+/// resource type has its own order, which we don't know, so keys stay in the order they're in.
+/// Files from before 2.3 are left alone, since that IDE can't read trailing commas.
+pub(crate) fn format_yy(source: &str, lang_config: &LangConfig) -> Result<String, YyError> {
+    let layout = SourceLayout::detect(source);
+    let text = line_endings::strip_bom(source);
+
+    let mut reader = Reader { text, position: 0 };
+    let json = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position != text.len() {
+        return Err(reader.error("expected the end of the file"));
+    }
+
+    let members = match &json {
+        Json::Object(members) => members,
+        _ => return Ok(source.to_owned()),
+    };
+    if members.iter().any(|(key, _)| *key == "\"mvc\"") {
+        return Ok(source.to_owned());
+    }
+    // 2023 and later projects (whose keys start like `"$GMScript"`) don't put a space after
+    // colons, and sort their keys.
+    let is_2023 = members.iter().any(|(key, _)| key.starts_with("\"$"));
+    let colon = if is_2023 { ":" } else { ": " };
+    let mut json = json;
+    if is_2023 {
+        sort_keys(&mut json);
+    }
+
+    let mut output = String::with_capacity(source.len());
+    write_expanded(&mut output, &json, 0, colon);
+    if text.ends_with('\n') {
+        output.push('\n');
+    }
+
+    Ok(layout.restore(output, lang_config.line_endings))
+}
+
+/// Describes why `source`, the contents of `this_file`, couldn't be formatted.
+pub(crate) fn describe_error(this_file: &Path, source: &str, error: &YyError) -> Diagnostic {
+    let text = line_endings::strip_bom(source);
+    let line = text[..error.offset].matches('\n').count() as u32;
+    let column = text[text[..error.offset].rfind('\n').map_or(0, |i| i + 1)..error.offset]
+        .chars()
+        .count() as u32;
+
+    Diagnostic::new(Severity::Error, this_file, error.message.to_owned()).at(Position::new(text, line, column, 1))
+}
+
+/// Sorts the keys of every object in `json`, by the bytes of their names.
+fn sort_keys(json: &mut Json<'_>) {
+    match json {
+        Json::Object(members) => {
+            members.sort_by_key(|(key, _)| *key);
+            for (_, value) in members {
+                sort_keys(value);
+            }
+        }
+        Json::Array(elements) => elements.iter_mut().for_each(sort_keys),
+        Json::Scalar(_) => {}
+    }
+}
+
+/// Writes an object with each member on its own line, or an array, starting at `indent`.
+fn write_expanded(output: &mut String, json: &Json<'_>, indent: usize, colon: &str) {
+    match json {
+        Json::Object(members) if members.is_empty() => output.push_str("{}"),
+        Json::Object(members) => {
+            output.push_str("{\n");
+            for (key, value) in members {
+                let _ = write!(output, "{:indent$}{}{}", "", key, colon, indent = indent + 2);
+                write_expanded(output, value, indent + 2, colon);
+                output.push_str(",\n");
+            }
+            let _ = write!(output, "{:indent$}}}", "", indent = indent);
+        }
+        Json::Array(elements) if elements.is_empty() => output.push_str("[]"),
+        Json::Array(elements) => {
+            output.push_str("[\n");
+            for element in elements {
+                let _ = write!(output, "{:indent$}", "", indent = indent + 2);
+                match element {
+                    Json::Object(_) => write_compact(output, element, indent + 2),
+                    _ => write_expanded(output, element, indent + 2, colon),
+                }
+                output.push_str(",\n");
+            }
+            let _ = write!(output, "{:indent$}]", "", indent = indent);
+        }
+        Json::Scalar(text) => output.push_str(text),
+    }
+}
+
+/// Writes an object on one line, like `{"name":"obj_player","path":"objects/obj_player/obj_player.yy",}`.
+/// Arrays inside it still get a line per element, indented past the line the object is on.
+fn write_compact(output: &mut String, json: &Json<'_>, indent: usize) {
+    match json {
+        Json::Object(members) => {
+            output.push('{');
+            for (key, value) in members {
+                output.push_str(key);
+                output.push(':');
+                write_compact(output, value, indent);
+                output.push(',');
+            }
+            output.push('}');
+        }
+        _ => write_expanded(output, json, indent + 2, ":"),
+    }
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn value(&mut self) -> Result<Json<'a>, YyError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                while self.end_of_list('}', members.is_empty())? == false {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return Err(self.error("expected a key"));
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.peek() != Some(':') {
+                        return Err(self.error("expected `:` after the key"));
+                    }
+                    self.position += 1;
+                    members.push((key, self.value()?));
+                }
+                Ok(Json::Object(members))
+            }
+            Some('[') => {
+                self.position += 1;
+                let mut elements = Vec::new();
+                while self.end_of_list(']', elements.is_empty())? == false {
+                    elements.push(self.value()?);
+                }
+                Ok(Json::Array(elements))
+            }
+            Some('"') => Ok(Json::Scalar(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_alphanumeric() => {
+                let start = self.position;
+                let length = self.text[start..]
+                    .find(|c: char| (c == '-' || c == '+' || c == '.' || c.is_ascii_alphanumeric()) == false)
+                    .unwrap_or(self.text.len() - start);
+                self.position += length;
+                Ok(Json::Scalar(&self.text[start..self.position]))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Steps past the comma after a member or element (which may be the trailing one), and
+    /// says whether `close` ends the list.
+    fn end_of_list(&mut self, close: char, first: bool) -> Result<bool, YyError> {
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.position += 1;
+            return Ok(true);
+        }
+
+        if first == false {
+            if self.peek() != Some(',') {
+                return Err(self.error("expected `,`"));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            if self.peek() == Some(close) {
+                self.position += 1;
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn string(&mut self) -> Result<&'a str, YyError> {
+        let start = self.position;
+        let mut escaped = false;
+
+        for (i, c) in self.text[start + 1..].char_indices() {
+            match c {
+                '"' if escaped == false => {
+                    self.position = start + 1 + i + 1;
+                    return Ok(&self.text[start..self.position]);
+                }
+                '\\' => escaped = escaped == false,
+                _ => escaped = false,
+            }
+        }
+
+        Err(self.error("this string is never closed"))
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.position = self.text.len() - self.text[self.position..].trim_start().len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn error(&self, message: &'static str) -> YyError {
+        YyError {
+            offset: self.position,
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod yy_test {
+    use super::*;
+
+    #[test]
+    fn ide_layout() {
+        let source = r#"{"isDnD": false, "parent": {"name": "Scripts", "path": "folders/Scripts.yy"},
+            "resources": [{"id": {"name": "obj_a", "path": "objects/obj_a/obj_a.yy"}, "order": 0}],
+            "layers": [{"instances": [{"x": 1.50, "tags": []}], "depth": -100}], "tags": ["a"], "empty": {}}"#;
+
+        assert_eq!(
+            format_yy(source, &LangConfig::default()).unwrap(),
+            r#"{
+  "isDnD": false,
+  "parent": {
+    "name": "Scripts",
+    "path": "folders/Scripts.yy",
+  },
+  "resources": [
+    {"id":{"name":"obj_a","path":"objects/obj_a/obj_a.yy",},"order":0,},
+  ],
+  "layers": [
+    {"instances":[
+        {"x":1.50,"tags":[],},
+      ],"depth":-100,},
+  ],
+  "tags": [
+    "a",
+  ],
+  "empty": {},
+}"#
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let ide = "{\r\n  \"$GMScript\":\"\",\r\n  \"%Name\":\"scr_a\",\r\n  \"parent\":{\r\n    \"name\":\"Scripts\",\r\n  },\r\n  \"tags\":[],\r\n}\r\n";
+        assert_eq!(format_yy(ide, &LangConfig::default()).unwrap(), ide);
+
+        // a merge tool put these keys out of order.
+        let reordered = "{\"parent\":{\"path\":\"folders/Scripts.yy\",\"name\":\"Scripts\",},\"%Name\":\"scr_a\",\"$GMScript\":\"\",\"isDnD\":false,\"isCompatibility\":false,}";
+        assert_eq!(
+            format_yy(reordered, &LangConfig::default()).unwrap(),
+            "{\n  \"$GMScript\":\"\",\n  \"%Name\":\"scr_a\",\n  \"isCompatibility\":false,\n  \"isDnD\":false,\n  \"parent\":{\n    \"name\":\"Scripts\",\n    \"path\":\"folders/Scripts.yy\",\n  },\n}"
+        );
+
+        let gms_22 = "{\n    \"id\": \"1\",\n    \"modelName\": \"GMScript\",\n    \"mvc\": \"1.0\"\n}";
+        assert_eq!(format_yy(gms_22, &LangConfig::default()).unwrap(), gms_22);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| format_yy(source, &LangConfig::default()).unwrap_err();
+
+        assert_eq!(
+            error("{\"a\": 1 \"b\": 2}"),
+            YyError {
+                offset: 8,
+                message: "expected `,`"
+            }
+        );
+        assert_eq!(error("{\"a\": [1,,]}").message, "expected a value");
+        assert_eq!(error("{\"a\": \"b}").message, "this string is never closed");
+        assert_eq!(error("{} {}").message, "expected the end of the file");
+
+        let source = "{\n  \"a\" 1,\n}";
+        let diagnostic = describe_error(Path::new("a.yy"), source, &error(source));
+        assert_eq!(
+            diagnostic.position.map(|position| (position.line, position.column)),
+            Some((1, 6))
+        );
+    }
+}
@@ -4,10 +4,16 @@ const LANG_CONFIG: LangConfig = LangConfig {
     use_spaces: true,
     space_size: 4,
     newlines_at_end: 1,
+    line_endings: LineEndings::Auto,
+    encoding: None,
+    recover_from_errors: false,
+    dialect: Dialect::Gms2,
 };
 
 fn run_test(input: &str) -> String {
-    run(input, &LANG_CONFIG, None).expect("Panicked during Integration Test!")
+    let output = run(input, &LANG_CONFIG, None).expect("Panicked during Integration Test!");
+    check_equivalence(input, &output).expect("Formatting changed the tokens of the input!");
+    output
 }
 
 #[test]
@@ -506,3 +512,231 @@ audio_stop_sound(aMusicTitle);
 
     assert_eq!(run_test(input), output);
 }
+
+#[test]
+fn crlf_preserved() {
+    let input = "// comment\r\nif (x)\r\n{\r\ny = 2\r\nz = 3;\r\n}\r\n";
+    let format = "// comment\r\nif (x) {\r\n    y = 2;\r\n    z = 3;\r\n}\r\n";
+
+    assert_eq!(run_test(input), format);
+}
+
+#[test]
+fn crlf_macro_continuation() {
+    let input = "#macro A 1 \\\r\n + 2\r\n#region r\r\nx = 1\r\n";
+    let format = "#macro A 1 \\\r\n + 2\r\n#region r\r\nx = 1;\r\n";
+
+    assert_eq!(run_test(input), format);
+}
+
+#[test]
+fn forced_line_endings() {
+    let input = "x = 1;\r\ny = 2;\r\n";
+    let lf_config = LangConfig {
+        line_endings: LineEndings::Lf,
+        ..LangConfig::default()
+    };
+    assert_eq!(run(input, &lf_config, None).unwrap(), "x = 1;\ny = 2;\n");
+
+    let crlf_config = LangConfig {
+        line_endings: LineEndings::Crlf,
+        ..LangConfig::default()
+    };
+    assert_eq!(run("x = 1;\ny = 2;\n", &crlf_config, None).unwrap(), input);
+}
+
+#[test]
+fn bom_preserved() {
+    let input = "\u{feff}x=1";
+    let format = "\u{feff}x = 1;\n";
+
+    assert_eq!(run_test(input), format);
+}
+
+#[test]
+fn recover_from_errors() {
+    let input = "switch (a) {
+  ??
+  case 1: b=2; break;
+}
+if (q) {
+x=1
+y=2
+}
+v =
+";
+    let format = "switch (a) {
+  ??
+  case 1: b=2; break;
+}
+if (q) {
+    x = 1;
+    y = 2;
+}
+v =
+";
+    assert!(run(input, &LANG_CONFIG, None).is_err());
+
+    let recovering_config = LangConfig {
+        recover_from_errors: true,
+        ..LangConfig::default()
+    };
+    let output = run(input, &recovering_config, None).unwrap();
+    check_equivalence(input, &output).unwrap();
+    assert_eq!(output, format);
+}
+
+#[test]
+fn gms1_dialect() {
+    let input = "path=\"C:\\\"\nmessage='one\ntwo'; // still code\nx=1";
+    let format = "path = \"C:\\\";\nmessage = 'one\ntwo'; // still code\nx = 1;\n";
+
+    let gms1_config = LangConfig::from_toml("dialect = \"gms1\"").unwrap();
+    assert_eq!(gms1_config.dialect, Dialect::Gms1);
+    let output = run(input, &gms1_config, None).unwrap();
+    check_equivalence_in(input, &output, Dialect::Gms1).unwrap();
+    assert_eq!(output, format);
+}
+
+#[test]
+fn recover_from_errors_in_cases() {
+    let recovering_config = LangConfig {
+        recover_from_errors: true,
+        ..LangConfig::default()
+    };
+
+    let input = "switch (a) {\ncase 1: x = ;\ncase 2: y = 1;\n}\n";
+    let format = "switch (a) {\n    case 1:\n        x = ;\n    case 2:\n        y = 1;\n}\n";
+    let output = run(input, &recovering_config, None).unwrap();
+    check_equivalence(input, &output).unwrap();
+    assert_eq!(output, format);
+
+    let format = "switch (a) {\n    case 1:\n        ??\n}\n";
+    let input = "switch (a) {\n case 1: ?? }\n";
+    let output = run(input, &recovering_config, None).unwrap();
+    check_equivalence(input, &output).unwrap();
+    assert_eq!(output, format);
+
+    // we close the switch the file never did, which `check_equivalence` refuses to write, but
+    // the code in the case is still there.
+    assert_eq!(
+        run("switch (a) {\n case 1: ??", &recovering_config, None).unwrap(),
+        format
+    );
+}
+
+#[test]
+fn public_ast() {
+    let input = "var a = 1;\nshow_debug_message(a)\n";
+    let ast = parse(input).unwrap();
+
+    let calls: Vec<&str> = ast
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.statement {
+            Statement::ExpresssionStatement { expression } => match &expression.expr {
+                Expr::Call { procedure_name, .. } => {
+                    Some(&ast.source()[procedure_name.span.start..procedure_name.span.end])
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    assert_eq!(calls, vec!["show_debug_message"]);
+
+    assert_eq!(format_ast(&ast, &LANG_CONFIG), run_test(input));
+    assert!(parse("switch (a) { ?? }").is_err());
+    assert!(parse_with_recovery("switch (a) { ?? }").is_ok());
+}
+
+#[test]
+fn comments_without_a_place_in_the_ast() {
+    let input = "switch (a) {\ndefault /* why */:\n    x();\n    y();\n}\n";
+    let format = "switch (a) {
+    default /* why */:
+        x();
+        y();
+}
+";
+    assert_eq!(run_test(input), format);
+    assert_eq!(run_test(format), format);
+
+    let cst = Cst::parse(input);
+    assert_eq!(cst.to_string(), input);
+}
+
+#[test]
+fn gmedit_headers() {
+    let input = "#event create
+x=1
+y=2
+
+#section movement
+spd=4
+#event step_begin   
+if(a){
+z=3
+w=1
+}
+#event collision:obj_wall
+#target windows
+q=1
+";
+    let format = "#event create
+x = 1;
+y = 2;
+
+#section movement
+spd = 4;
+#event step_begin
+if (a) {
+    z = 3;
+    w = 1;
+}
+#event collision:obj_wall
+#target windows
+q = 1;
+";
+    assert_eq!(run_test(input), format);
+    assert_eq!(run_test(format), format);
+
+    let ast = parse(input).unwrap();
+    let headers: Vec<&str> = ast
+        .statements
+        .iter()
+        .filter_map(|stmt| match &stmt.statement {
+            Statement::Header { header, .. } => Some(&ast.source()[header.span.start..header.span.end]),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        headers,
+        vec![
+            "#event create",
+            "#section movement",
+            "#event step_begin",
+            "#event collision:obj_wall",
+            "#target windows"
+        ]
+    );
+}
+
+#[test]
+fn gmedit_header_in_block() {
+    let input = "if (a) {
+#section foo
+x=1;
+y=2;
+}
+z=3;
+";
+    let format = "if (a) {
+    #section foo
+    x = 1;
+    y = 2;
+}
+z = 3;
+";
+    assert_eq!(run_test(input), format);
+}